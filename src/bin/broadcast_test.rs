@@ -5,11 +5,11 @@ async fn main() {
     let (brd_tx, mut brd_rx) = broadcast::channel(100);
 
     
-    for i in (0..10) {
+    for i in 0..10 {
         let mut rx = brd_tx.subscribe();
         tokio::spawn(async move {
             loop {
-                if let Ok(msg) = rx.recv().await {
+                if let Ok(_msg) = rx.recv().await {
                     println!("bye from task {}", i);
                     break;
                 }
@@ -22,7 +22,7 @@ async fn main() {
     }).unwrap();
 
     loop {
-        if let Ok(msg) = brd_rx.recv().await {
+        if let Ok(_msg) = brd_rx.recv().await {
             println!("bye");
             break;
         }
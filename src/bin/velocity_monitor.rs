@@ -0,0 +1,12 @@
+use std::error::Error;
+use log::Level;
+use lp_pro_gamecontroller::{config::Config, open_device_pair_with_event_loop, velocity_monitor_loop};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+
+    simple_logger::init_with_level(Level::Info)?;
+
+    let config = Config::init("./Mapping.toml").await?;
+    open_device_pair_with_event_loop(config.device, velocity_monitor_loop).await
+}
@@ -1,19 +1,592 @@
-use std::{collections::HashMap, error::Error};
+use std::{collections::HashMap, error::Error, fmt, time::Duration};
 
-use serde::{Deserialize, Serialize};
+use serde::{
+    Deserialize, Serialize,
+    de::{self, IntoDeserializer, Visitor},
+};
 use tokio::{fs::File, io::AsyncReadExt};
 
-use crate::{DeviceNameRetrieve, midi::{note::{MidiNote, set_mapping}, output::OutputDeviceNameRetrieve}};
+use crate::{DeviceNameRetrieve, midi::{input::InputSettingsRetrieve, mapping::{MappingKey, PadChord, set_mapping}, output::OutputDeviceNameRetrieve}};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "lowercase")]
 pub enum Action {
-    Key(rdev::Key),
+    /// A single key or a chord like `Ctrl+Shift+KeyT`
+    Key(KeyChord),
+    /// Commands run on press and release
     Shell {
-        press: Option<String>,
-        release: Option<String>
+        press: Option<Box<ShellCommand>>,
+        release: Option<Box<ShellCommand>>,
+
+        /// The press command runs while the pad is held and is terminated on release
+        #[serde(default)]
+        hold: bool,
+
+        /// Presses while the press command is still running are ignored
+        #[serde(default)]
+        single_instance: bool,
+
+        /// Terminates commands running longer than this
+        timeout_ms: Option<u64>,
+
+        /// Where the standard output and error of the commands go
+        #[serde(default)]
+        output: CommandOutput,
     },
 
+    /// Moves the cursor by the change of a continuous control. rdev can only place the cursor absolutely,
+    /// so the motion starts from where the driver last put it and moving the physical mouse is not noticed
+    Mouse(AxisOptions),
+
+    /// Scroll wheel ticks driven by a continuous control
+    Scroll(AxisOptions),
+
+    /// Absolute cursor position driven by a continuous control
+    Position(AxisOptions),
+
+    /// Activates a layer from the `[layers]` table
+    Layer(LayerSwitch),
+
+    /// Timed steps running in the background
+    Sequence(Sequence),
+
+    /// Different actions for tapping, holding and double tapping
+    Gesture(Gestures),
+
+    /// Makes other pads use their alternative action while held, sends nothing itself
+    Modifier(Modifier),
+
+    /// Types a string in the background using the layout of `[device.text]`
+    Text(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Modifier {
+    /// Switches to the `shift` action of a binding
+    Shift,
+}
+
+/// A command of a shell action, either just the command line or with options
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ShellCommand {
+    Line(CommandLine),
+    Detailed(CommandOptions),
+}
+
+impl ShellCommand {
+    pub fn options(&self) -> CommandOptions {
+        match self {
+            ShellCommand::Line(command) => CommandOptions {
+                command: command.clone(),
+                env: HashMap::new(),
+                cwd: None,
+                stdin: None,
+            },
+            ShellCommand::Detailed(options) => options.clone(),
+        }
+    }
+}
+
+/// A string run through `sh -c`, or a program followed by its arguments
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum CommandLine {
+    Shell(String),
+    Argv(Vec<String>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CommandOptions {
+    pub command: CommandLine,
+
+    /// Variables added to the environment of the driver
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+
+    /// Working directory, defaults to the one of the driver
+    pub cwd: Option<String>,
+
+    /// Written to the standard input of the command
+    pub stdin: Option<String>,
+}
+
+/// Destination of the standard output and error of shell commands
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CommandOutput {
+    /// Shared with the console of the driver
+    #[default]
+    Inherit,
+
+    /// Every line is logged with the pad as context, errors as warnings
+    Log,
+
+    /// Appended to the file together with the exit status of each run
+    File(String),
+
+    Discard,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Gestures {
+    /// Pressed and released when the pad is released before the hold time
+    pub tap: Option<Box<Action>>,
+
+    /// Pressed once the pad is held for the hold time, released with the pad
+    pub hold: Option<Box<Action>>,
+
+    /// Pressed on the second press within the double tap time, released with the pad
+    pub double_tap: Option<Box<Action>>,
+
+    /// Overrides `hold_ms` of `[device.timing]`
+    pub hold_ms: Option<u64>,
+
+    /// Overrides `double_tap_ms` of `[device.timing]`
+    pub double_tap_ms: Option<u64>,
+}
+
+impl Gestures {
+    pub fn hold_time(&self, timing: &Timing) -> Duration {
+        Duration::from_millis(self.hold_ms.unwrap_or(timing.hold_ms))
+    }
+
+    pub fn double_tap_time(&self, timing: &Timing) -> Duration {
+        Duration::from_millis(self.double_tap_ms.unwrap_or(timing.double_tap_ms))
+    }
+}
+
+/// Default gesture timings in milliseconds
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Timing {
+    pub hold_ms: u64,
+    pub double_tap_ms: u64,
+
+    /// Window in which all pads of a chord have to be pressed
+    pub chord_ms: u64,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Timing {
+            hold_ms: 250,
+            double_tap_ms: 250,
+            chord_ms: 50,
+        }
+    }
+}
+
+/// How strings of text actions and sequences are typed
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct TextSettings {
+    /// Keyboard layout the host is set to
+    pub layout: KeyboardLayout,
+
+    /// What happens to characters the layout has no keys for
+    pub fallback: TextFallback,
+
+    /// Pause after every character
+    pub delay_ms: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyboardLayout {
+    #[default]
+    Us,
+    De,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TextFallback {
+    /// The character is left out
+    #[default]
+    Skip,
+
+    /// Entered by its code point with Ctrl+Shift+U, understood by GTK and IBus
+    Unicode,
+}
+
+/// Shapes the velocity of incoming hits before anything else sees them
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(tag = "curve", rename_all = "lowercase")]
+pub enum VelocityCurve {
+    #[default]
+    Linear,
+
+    /// Soft hits get softer, `exponent` above 1 needs harder hits
+    Exponential {
+        #[serde(default = "default_exponent")]
+        exponent: f64,
+    },
+
+    /// Soft hits get louder
+    Log,
+
+    /// Output velocities spread evenly over the input range, interpolated in between
+    Table { points: Vec<u8> },
+
+    /// Every hit has the same velocity
+    Fixed { value: u8 },
+}
+
+fn default_exponent() -> f64 {
+    2.0
+}
+
+impl VelocityCurve {
+    pub fn apply(&self, velocity: u8) -> u8 {
+        let x = velocity.min(127) as f64 / 127.0;
+        let y = match self {
+            VelocityCurve::Linear => return velocity,
+            VelocityCurve::Exponential { exponent } => x.powf(*exponent),
+            VelocityCurve::Log => (1.0 + 9.0 * x).log10(),
+            VelocityCurve::Table { points } => match points.as_slice() {
+                [] => return velocity,
+                [only] => *only as f64 / 127.0,
+                points => {
+                    let position = x * (points.len() - 1) as f64;
+                    let i = (position.floor() as usize).min(points.len() - 2);
+                    let t = position - i as f64;
+                    (points[i] as f64 * (1.0 - t) + points[i + 1] as f64 * t) / 127.0
+                }
+            },
+            VelocityCurve::Fixed { value } => *value as f64 / 127.0,
+        };
+
+        // a NoteOn with velocity 0 would be a NoteOff
+        ((y * 127.0).round() as u8).clamp(1, 127)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Sequence {
+    pub steps: Vec<Step>,
+
+    /// What a press does while the sequence is still running
+    #[serde(default)]
+    pub retrigger: Retrigger,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum Step {
+    Press(KeyChord),
+    Release(KeyChord),
+
+    /// Press and release
+    Tap(KeyChord),
+
+    /// Pause in milliseconds
+    Wait(u64),
+
+    /// Type a string
+    Text(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Retrigger {
+    /// Stop the running sequence and start over
+    Restart,
+
+    /// Run again after the running sequence finished
+    Queue,
+
+    #[default]
+    Ignore,
+}
+
+/// Keys pressed in order and released in reverse order
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct KeyChord(pub Vec<rdev::Key>);
+
+impl From<rdev::Key> for KeyChord {
+    fn from(value: rdev::Key) -> Self {
+        KeyChord(vec![value])
+    }
+}
+
+impl KeyChord {
+    /// Parses `Ctrl+Shift+KeyT`, tokens are either modifier shorthands or [rdev::Key] names
+    pub fn parse(value: &str) -> Result<Self, String> {
+        value
+            .split('+')
+            .map(|token| parse_key(token).map_err(|e| format!("{} in `{}`", e, value)))
+            .collect::<Result<_, _>>()
+            .map(KeyChord)
+    }
+}
+
+/// Parses a single key name, accepting shorthands for the left modifiers
+pub fn parse_key(token: &str) -> Result<rdev::Key, String> {
+    let token = token.trim();
+
+    let key = match token.to_lowercase().as_str() {
+        "ctrl" | "control" => rdev::Key::ControlLeft,
+        "shift" => rdev::Key::ShiftLeft,
+        "alt" => rdev::Key::Alt,
+        "altgr" => rdev::Key::AltGr,
+        "meta" | "super" | "win" | "cmd" => rdev::Key::MetaLeft,
+        _ => rdev::Key::deserialize(token.into_deserializer())
+            .map_err(|_: de::value::Error| format!("unknown key `{}`", token))?,
+    };
+
+    Ok(key)
+}
+
+impl<'de> Deserialize<'de> for KeyChord {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct ChordVisitor;
+
+        impl<'de> Visitor<'de> for ChordVisitor {
+            type Value = KeyChord;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a key like KeyW, a chord like Ctrl+Shift+KeyT or a list of keys")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                KeyChord::parse(v).map_err(E::custom)
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: de::SeqAccess<'de>,
+            {
+                let mut keys = Vec::new();
+                while let Some(token) = seq.next_element::<String>()? {
+                    keys.push(parse_key(&token).map_err(de::Error::custom)?);
+                }
+
+                if keys.is_empty() {
+                    return Err(de::Error::custom("empty key list"));
+                }
+                Ok(KeyChord(keys))
+            }
+        }
+
+        deserializer.deserialize_any(ChordVisitor)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LayerSwitch {
+    pub name: String,
+
+    #[serde(default)]
+    pub mode: LayerMode,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LayerMode {
+    /// Active while the pad is held
+    #[default]
+    Hold,
+
+    /// Every press switches the layer on or off
+    Toggle,
+
+    /// Active for the next pad press only
+    OneShot,
+}
+
+/// An entry of the `[mapping]` table: the action and options on how it is triggered
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Binding {
+    #[serde(flatten)]
+    pub action: Action,
+
+    /// Additional action fired while the pad is pressed hard enough
+    pub pressure: Option<PressureBinding>,
+
+    /// Repeatedly taps a key action while the pad is held
+    pub turbo: Option<Turbo>,
+
+    /// First press keeps the action active, the next press releases it
+    #[serde(default)]
+    pub latch: bool,
+
+    /// Hits softer than this are ignored
+    pub min_velocity: Option<u8>,
+
+    /// Actions replacing the main one for hits within a velocity range
+    #[serde(default)]
+    pub zones: Vec<VelocityZone>,
+
+    /// Replaces the action while a shift modifier pad is held
+    pub shift: Option<Box<Action>>,
+}
+
+impl Binding {
+    /// The binding with the action of the zone the velocity falls into
+    pub fn for_velocity(mut self, velocity: u8) -> Binding {
+        let zone = self
+            .zones
+            .iter()
+            .find(|zone| (zone.velocity[0]..=zone.velocity[1]).contains(&velocity));
+        if let Some(zone) = zone {
+            self.action = zone.action.clone();
+        }
+        self
+    }
+
+    /// The binding with its `shift` action, if it has one
+    pub fn shifted(mut self) -> Binding {
+        if let Some(shift) = self.shift.take() {
+            self.action = *shift;
+        }
+        self
+    }
+}
+
+/// An action for hits within an inclusive velocity range
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VelocityZone {
+    pub velocity: [u8; 2],
+
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Turbo {
+    /// Taps per second, at full velocity or pressure if scaled
+    pub rate: f64,
+
+    /// Taps per second at the lowest velocity or pressure if scaled
+    #[serde(default = "default_min_rate")]
+    pub min_rate: f64,
+
+    #[serde(default)]
+    pub scale: TurboScale,
+}
+
+fn default_min_rate() -> f64 {
+    1.0
+}
+
+impl Turbo {
+    /// Rate for a velocity or pressure value (0 - 127)
+    pub fn rate(&self, value: u8) -> f64 {
+        match self.scale {
+            TurboScale::Fixed => self.rate,
+            _ => self.min_rate + (self.rate - self.min_rate) * value as f64 / 127.0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TurboScale {
+    #[default]
+    Fixed,
+
+    /// Rate follows the velocity of the press
+    Velocity,
+
+    /// Rate follows polyphonic or channel pressure while held
+    Pressure,
+}
+
+/// Action engaged by polyphonic or channel pressure crossing a threshold
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PressureBinding {
+    /// Pressure (0 - 127) at which the action is pressed
+    pub threshold: u8,
+
+    /// How far the pressure has to drop below the threshold to release the action again
+    #[serde(default = "default_hysteresis")]
+    pub hysteresis: u8,
+
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+fn default_hysteresis() -> u8 {
+    10
+}
+
+/// Action triggered by pads pressed in order, like down, down forward, forward, punch
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Combo {
+    pub steps: Vec<MappingKey>,
+
+    /// Longest time between two steps in milliseconds
+    #[serde(default = "default_max_gap_ms")]
+    pub max_gap_ms: u64,
+
+    /// Whether the steps after the first still send their own actions
+    #[serde(default = "default_passthrough")]
+    pub passthrough: bool,
+
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+fn default_max_gap_ms() -> u64 {
+    200
+}
+
+fn default_passthrough() -> bool {
+    true
+}
+
+impl Action {
+    /// Continuous actions consume every value of a control instead of press/release
+    pub fn is_continuous(&self) -> bool {
+        matches!(self, Action::Mouse(_) | Action::Scroll(_) | Action::Position(_))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Axis {
+    X,
+    Y,
+}
+
+/// Options for actions driven by faders, knobs or pitch bend
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AxisOptions {
+    pub axis: Axis,
+
+    /// Pixels (mouse) or wheel ticks (scroll) per full travel of the control
+    pub scale: Option<f64>,
+
+    /// Values this close to the center (0.0 - 0.5) are treated as the center
+    #[serde(default)]
+    pub deadzone: f64,
+
+    /// Output range of absolute positions in pixels, defaults to the display size
+    pub range: Option<[f64; 2]>,
+
+    #[serde(default)]
+    pub invert: bool,
+}
+
+impl AxisOptions {
+    /// Applies deadzone and inversion to a value normalized to 0.0..=1.0
+    pub fn shape(&self, value: f64) -> f64 {
+        let value = if self.invert { 1.0 - value } else { value };
+
+        if (value - 0.5).abs() <= self.deadzone {
+            0.5
+        } else {
+            value
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -21,6 +594,30 @@ pub struct DeviceConfig {
     input: Option<String>,
     output: Option<String>,
     pub lights: bool,
+    #[serde(default)]
+    pub timing: Timing,
+
+    /// Pads that release all keys and stop everything running when held together
+    #[serde(default)]
+    pub panic: Vec<MappingKey>,
+
+    /// Pads that suspend and resume all actions when held together
+    #[serde(default)]
+    pub pause: Vec<MappingKey>,
+
+    /// Pairs of opposite directions, by name
+    #[serde(default)]
+    pub socd: HashMap<String, SocdGroup>,
+
+    /// Groups of mutually exclusive pads, by name
+    #[serde(default)]
+    pub radio: HashMap<String, RadioGroup>,
+
+    #[serde(default)]
+    pub velocity: VelocityCurve,
+
+    #[serde(default)]
+    pub text: TextSettings,
 }
 
 impl DeviceNameRetrieve for DeviceConfig {
@@ -39,6 +636,69 @@ impl OutputDeviceNameRetrieve for DeviceConfig {
     }
 }
 
+impl InputSettingsRetrieve for DeviceConfig {
+    fn get_timing(&self) -> Timing {
+        self.timing.clone()
+    }
+
+    fn get_panic_combo(&self) -> Vec<MappingKey> {
+        self.panic.clone()
+    }
+
+    fn get_pause_combo(&self) -> Vec<MappingKey> {
+        self.pause.clone()
+    }
+
+    fn get_socd_groups(&self) -> Vec<SocdGroup> {
+        self.socd.values().cloned().collect()
+    }
+
+    fn get_radio_groups(&self) -> Vec<RadioGroup> {
+        self.radio.values().cloned().collect()
+    }
+
+    fn get_velocity_curve(&self) -> VelocityCurve {
+        self.velocity.clone()
+    }
+
+    fn get_text_settings(&self) -> TextSettings {
+        self.text.clone()
+    }
+}
+
+/// Two pads of opposite directions that must not be active at the same time
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SocdGroup {
+    pub pads: [MappingKey; 2],
+    #[serde(default)]
+    pub resolution: SocdResolution,
+}
+
+/// Which direction is in effect while both are held
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SocdResolution {
+    /// The pad pressed last
+    #[default]
+    Last,
+
+    /// Neither of them
+    Neutral,
+
+    /// The pad pressed first
+    First,
+}
+
+/// Pads of which only one is selected at a time
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RadioGroup {
+    pub pads: Vec<MappingKey>,
+
+    /// Keep the action of the selected pad active until another one is selected
+    #[serde(default)]
+    pub hold: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Integration {
     input: String,
@@ -55,9 +715,26 @@ impl DeviceNameRetrieve for Integration {
     }
 }
 
+/// A named set of bindings that can be stacked on top of `[mapping]`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Layer {
+    #[serde(default)]
+    mapping: HashMap<MappingKey, Binding>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Config {
-    mapping: HashMap<MidiNote, Action>,
+    mapping: HashMap<MappingKey, Binding>,
+    #[serde(default)]
+    layers: HashMap<String, Layer>,
+
+    /// Bindings of several pads pressed together
+    #[serde(default)]
+    chords: HashMap<PadChord, Binding>,
+
+    /// Actions of pads pressed in order
+    #[serde(default)]
+    combos: Vec<Combo>,
     pub integration: Integration,
     //device: HashMap<String, String>,
     pub device: DeviceConfig,
@@ -67,14 +744,122 @@ impl Config {
     pub async fn init(path: &str) -> Result<Self, Box<dyn Error>> {
         let mut input = File::open(path).await?;
         let mut s = String::new();
-        input
-            .read_to_string(&mut s)
-            .await
-            .expect("error reading mapping file");
-        let toml: Config = toml::from_str(&s).expect("failed parsing the config");
+        input.read_to_string(&mut s).await?;
+        let toml: Config = toml::from_str(&s)?;
 
-        set_mapping(toml.mapping.clone());
+        let layers = toml
+            .layers
+            .iter()
+            .map(|(name, layer)| (name.clone(), layer.mapping.clone()))
+            .collect();
+        set_mapping(toml.mapping.clone(), layers, toml.chords.clone(), toml.combos.clone());
 
         Ok(toml)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, Axis, AxisOptions, Binding, KeyChord, Modifier, VelocityCurve};
+    use rdev::Key;
+
+    fn axis(deadzone: f64, invert: bool) -> AxisOptions {
+        AxisOptions {
+            axis: Axis::X,
+            scale: None,
+            deadzone,
+            range: None,
+            invert,
+        }
+    }
+
+    #[test]
+    fn test_axis_shaping() {
+        let plain = axis(0.0, false);
+        assert_eq!(plain.shape(0.0), 0.0);
+        assert_eq!(plain.shape(64.0 / 127.0), 64.0 / 127.0);
+        assert_eq!(plain.shape(1.0), 1.0);
+
+        // the boundary of the deadzone still snaps to the center
+        let deadzone = axis(0.25, false);
+        assert_eq!(deadzone.shape(0.75), 0.5);
+        assert_eq!(deadzone.shape(0.25), 0.5);
+        assert_eq!(deadzone.shape(64.0 / 127.0), 0.5);
+        assert_eq!(deadzone.shape(0.8), 0.8);
+        assert_eq!(deadzone.shape(0.0), 0.0);
+
+        let inverted = axis(0.25, true);
+        assert_eq!(inverted.shape(0.0), 1.0);
+        assert_eq!(inverted.shape(1.0), 0.0);
+        assert_eq!(inverted.shape(0.75), 0.5);
+        assert_eq!(inverted.shape(0.125), 0.875);
+    }
+
+    #[test]
+    fn test_chord_parsing() {
+        let chord = KeyChord::parse("Ctrl+Shift+KeyT").unwrap();
+        assert_eq!(chord.0, vec![Key::ControlLeft, Key::ShiftLeft, Key::KeyT]);
+
+        let binding: Binding = toml::from_str(r#"key = ["Alt", "F4"]"#).unwrap();
+        assert!(matches!(binding.action, Action::Key(KeyChord(keys)) if keys == vec![Key::Alt, Key::F4]));
+    }
+
+    #[test]
+    fn test_velocity_zones() {
+        let binding: Binding = toml::from_str(
+            r#"
+            key = "KeyW"
+            min_velocity = 10
+            zones = [{ velocity = [90, 127], key = "Shift+KeyW" }]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(binding.min_velocity, Some(10));
+
+        let soft = binding.clone().for_velocity(40);
+        assert!(matches!(soft.action, Action::Key(KeyChord(keys)) if keys == vec![Key::KeyW]));
+        let hard = binding.for_velocity(90);
+        assert!(matches!(hard.action, Action::Key(KeyChord(keys)) if keys == vec![Key::ShiftLeft, Key::KeyW]));
+    }
+
+    #[test]
+    fn test_shift_action() {
+        let binding: Binding = toml::from_str(r#"key = "KeyQ"
+shift = { key = "KeyE" }"#).unwrap();
+        assert!(matches!(binding.shifted().action, Action::Key(KeyChord(keys)) if keys == vec![Key::KeyE]));
+
+        let modifier: Binding = toml::from_str(r#"modifier = "shift""#).unwrap();
+        assert!(matches!(modifier.clone().shifted().action, Action::Modifier(Modifier::Shift)));
+    }
+
+    #[test]
+    fn test_velocity_curves() {
+        let curve = |s: &str| toml::from_str::<VelocityCurve>(s).unwrap();
+
+        assert_eq!(curve(r#"curve = "linear""#).apply(64), 64);
+        assert_eq!(curve(r#"curve = "exponential""#).apply(64), 32);
+        assert_eq!(curve(r#"curve = "exponential""#).apply(1), 1);
+        assert!(curve(r#"curve = "log""#).apply(32) > 64);
+        assert_eq!(curve(r#"curve = "fixed"
+value = 100"#).apply(3), 100);
+        assert_eq!(curve(r#"curve = "fixed"
+value = 0"#).apply(64), 1);
+        assert_eq!(curve(r#"curve = "fixed"
+value = 200"#).apply(64), 127);
+
+        let table = curve(r#"curve = "table"
+points = [0, 100, 127]"#);
+        assert_eq!(table.apply(0), 1);
+        assert_eq!(table.apply(127), 127);
+        assert_eq!(table.apply(32), 50);
+    }
+
+    #[test]
+    fn test_chord_errors_name_token() {
+        let err = KeyChord::parse("Ctrl+Bogus").unwrap_err();
+        assert!(err.contains("`Bogus`"), "{}", err);
+
+        let err = toml::from_str::<Binding>(r#"key = "Ctrl+Shfit+KeyT""#).unwrap_err();
+        assert!(err.to_string().contains("`Shfit`"), "{}", err);
+    }
+}
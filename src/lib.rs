@@ -2,12 +2,14 @@
    include_str!("../README.md")
 )]
 
-use crate::midi::input::{daw_mode_task, input_task};
+use crate::midi::input::{InputSettingsRetrieve, input_task};
 use crate::midi::output::{OutputDeviceNameRetrieve, start_overlay_task};
 use crate::{
-    config::Config,
-    midi::{device, message::Message},
-    virtual_input::create_backend,
+    midi::{
+        device,
+        message::{Message, MidiMessage},
+    },
+    virtual_input::{create_backend, release_all},
 };
 use midir::MidiOutputConnection;
 use std::{
@@ -41,7 +43,7 @@ where
     C: DeviceNameRetrieve,
 {
     let (tx, from_device) = mpsc::channel(100);
-    let _in_port = device::connect_input(config.get_input_name(), tx)?;
+    device::connect_input(config.get_input_name(), tx)?;
     let out_port = device::connect_output(config.get_output_name())?;
 
     event_loop(config, from_device, out_port).await?;
@@ -49,9 +51,9 @@ where
 }
 
 pub async fn integration_event_loop<C>(
-    config: C,
-    from_raw_device: mpsc::Receiver<Message>,
-    output_port: MidiOutputConnection
+    _config: C,
+    _from_raw_device: mpsc::Receiver<Message>,
+    _output_port: MidiOutputConnection
 ) -> Result<(), RecvError>
 where 
     C: DeviceNameRetrieve + Send + Sync + Clone + 'static,
@@ -59,6 +61,34 @@ where
     Ok(())
 }
 
+/// Prints every hit with its velocity before and after the `[device.velocity]` curve
+pub async fn velocity_monitor_loop<C>(
+    config: C,
+    mut from_raw_device: mpsc::Receiver<Message>,
+    _output_port: MidiOutputConnection,
+) -> Result<(), RecvError>
+where
+    C: InputSettingsRetrieve + Send + Sync + Clone + 'static,
+{
+    let curve = config.get_velocity_curve();
+    println!("velocity curve: {:?}", curve);
+
+    while let Some(msg) = from_raw_device.recv().await {
+        if let MidiMessage::NoteOn(ch, note, vel) = msg.1 {
+            let shaped = curve.apply(vel);
+            println!(
+                "ch{:<2} {:<8} {:>3} -> {:>3} {}",
+                ch + 1,
+                format!("{:?}", note),
+                vel,
+                shaped,
+                "#".repeat(shaped as usize / 4)
+            );
+        }
+    }
+    Ok(())
+}
+
 /// The Event Loop that processes and sends events to their destinations
 pub async fn main_event_loop<C>(
     config: C,
@@ -66,12 +96,20 @@ pub async fn main_event_loop<C>(
     output_port: MidiOutputConnection,
 ) -> Result<(), RecvError>
 where
-    C: OutputDeviceNameRetrieve + Send + Sync + Clone + 'static,
+    C: OutputDeviceNameRetrieve + InputSettingsRetrieve + Send + Sync + Clone + 'static,
 {
     // cancellation signal that signals our tasks we are done
     let (cancellation, _rx) = broadcast::channel(1);
     let in_rx = cancellation.subscribe();
     let out_rx = cancellation.subscribe();
+    let stop_overlay = cancellation.clone();
+
+    // keys pressed through the backend would stay down on the host
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        release_all();
+        hook(info);
+    }));
 
     ctrlc::set_handler(move || {
         cancellation
@@ -87,7 +125,30 @@ where
     // feedback channel
     let (active_tx, active_rx) = broadcast::channel(100);
 
-    let input_task = tokio::spawn(input_task(from_raw_device, backend, active_tx, in_rx));
+    // `kill -USR1` toggles the pause like the pause combo
+    let (pause_tx, pause_rx) = mpsc::channel(1);
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let Ok(mut requests) = signal(SignalKind::user_defined1()) else {
+            return;
+        };
+        while requests.recv().await.is_some() {
+            let _ = pause_tx.send(()).await;
+        }
+    });
+    #[cfg(not(unix))]
+    drop(pause_tx);
+
+    let input_task = tokio::spawn(input_task(
+        config.clone(),
+        from_raw_device,
+        backend,
+        active_tx,
+        pause_rx,
+        in_rx,
+    ));
     let output_task = tokio::spawn(start_overlay_task(
         config.clone(),
         active_rx,
@@ -97,7 +158,10 @@ where
 
     //let daw_mode_in = tokio::spawn(daw_mode_task(from_raw_device, backend, internal_broadcast, cancellation))
 
-    let join = tokio::join!(input_task, output_task);
+    // the input task also ends when the device is gone, the overlay has to follow
+    let _input = input_task.await;
+    let _ = stop_overlay.send(());
+    let _output = output_task.await;
 
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,237 @@
+use std::time::Duration;
+
+use crate::midi::{
+    mapping::{Control, PadChord},
+    message::Message,
+};
+
+/// What the dispatcher has to do after feeding the detector
+#[derive(Debug, PartialEq)]
+pub enum ChordEvent {
+    /// A press for the per pad handling, held back presses come late
+    Press(Control, Message),
+
+    /// A release for the per pad handling
+    Release(Control, Message),
+
+    /// All pads of the chord with this index are down
+    ChordPress(usize, Vec<Control>),
+
+    /// The first pad of a pressed chord was released
+    ChordRelease(usize),
+}
+
+/// Result of feeding the detector, a timer has to report back through [ChordDetector::timeout]
+#[derive(Debug, Default, PartialEq)]
+pub struct ChordOutcome {
+    pub events: Vec<ChordEvent>,
+    pub timer: Option<(Duration, u64)>,
+}
+
+/// Holds back presses of chord pads until the chord completes or its window passed.
+/// Timestamps are the microseconds of [Message].
+pub struct ChordDetector {
+    chords: Vec<PadChord>,
+    window: Duration,
+
+    /// Presses held back, oldest first
+    pending: Vec<(Control, Message)>,
+
+    /// Pressed chords and their pads that are still down
+    active: Vec<(usize, Vec<Control>)>,
+
+    /// Chords whose first pad was released already
+    released: Vec<usize>,
+
+    generation: u64,
+}
+
+impl ChordDetector {
+    pub fn new(chords: Vec<PadChord>, window: Duration) -> Self {
+        Self {
+            chords,
+            window,
+            pending: Vec::new(),
+            active: Vec::new(),
+            released: Vec::new(),
+            generation: 0,
+        }
+    }
+
+    fn in_chord(&self, control: Control) -> bool {
+        self.chords.iter().any(|chord| chord.contains(control))
+    }
+
+    /// Hands on all held back presses
+    fn flush(&mut self) -> Vec<ChordEvent> {
+        self.generation += 1;
+        self.pending.drain(..).map(|(c, m)| ChordEvent::Press(c, m)).collect()
+    }
+
+    pub fn press(&mut self, control: Control, msg: Message) -> ChordOutcome {
+        let mut outcome = ChordOutcome::default();
+        if !self.in_chord(control) {
+            outcome.events = self.flush();
+            outcome.events.push(ChordEvent::Press(control, msg));
+            return outcome;
+        }
+
+        // the window passed before its timer was handled
+        if let Some((_, first)) = self.pending.first()
+            && msg.0.saturating_sub(first.0) > self.window.as_micros() as u64
+        {
+            outcome.events = self.flush();
+        }
+
+        self.pending.push((control, msg));
+        let pending: Vec<Control> = self.pending.iter().map(|(c, _)| *c).collect();
+
+        // the largest chord that is complete
+        let complete = self
+            .chords
+            .iter()
+            .enumerate()
+            .filter(|(_, chord)| chord.is_complete(&pending))
+            .max_by_key(|(_, chord)| chord.0.len())
+            .map(|(i, _)| i);
+        if let Some(index) = complete {
+            let (pads, rest): (Vec<_>, Vec<_>) = self
+                .pending
+                .drain(..)
+                .partition(|(c, _)| self.chords[index].contains(*c));
+            let pads: Vec<Control> = pads.into_iter().map(|(c, _)| c).collect();
+            self.pending = rest;
+            self.active.push((index, pads.clone()));
+
+            outcome.events.extend(self.flush());
+            outcome.events.push(ChordEvent::ChordPress(index, pads));
+            return outcome;
+        }
+
+        // no chord can be completed with the pads down
+        if !self.chords.iter().any(|chord| pending.iter().all(|c| chord.contains(*c))) {
+            outcome.events.extend(self.flush());
+            return outcome;
+        }
+
+        if self.pending.len() == 1 {
+            self.generation += 1;
+            outcome.timer = Some((self.window, self.generation));
+        }
+        outcome
+    }
+
+    pub fn release(&mut self, control: Control, msg: Message) -> ChordOutcome {
+        let mut outcome = ChordOutcome::default();
+
+        if let Some(position) = self.active.iter().position(|(_, pads)| pads.contains(&control)) {
+            let (index, pads) = &mut self.active[position];
+            let index = *index;
+            pads.retain(|c| *c != control);
+
+            if !self.released.contains(&index) {
+                self.released.push(index);
+                outcome.events.push(ChordEvent::ChordRelease(index));
+            }
+            if pads.is_empty() {
+                self.active.remove(position);
+                self.released.retain(|i| *i != index);
+            }
+            return outcome;
+        }
+
+        // a quick tap of a single chord pad
+        if self.pending.iter().any(|(c, _)| *c == control) {
+            outcome.events = self.flush();
+        }
+        outcome.events.push(ChordEvent::Release(control, msg));
+        outcome
+    }
+
+    /// A timer armed by an earlier outcome ran out
+    pub fn timeout(&mut self, generation: u64) -> Vec<ChordEvent> {
+        if generation != self.generation {
+            return vec![];
+        }
+        self.flush()
+    }
+
+    /// Forgets everything, held back presses are dropped
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.active.clear();
+        self.released.clear();
+        self.generation += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{ChordDetector, ChordEvent};
+    use crate::midi::{
+        mapping::{PadChord, Trigger},
+        message::{Message, MidiMessage},
+        note::MidiNote,
+    };
+
+    const MS: u64 = 1000;
+
+    fn detector() -> ChordDetector {
+        ChordDetector::new(vec![PadChord::parse("C3+E3+G3").unwrap()], Duration::from_millis(50))
+    }
+
+    fn press(note: MidiNote, at: u64) -> ((u8, Trigger), Message) {
+        ((0, note.into()), Message(at, MidiMessage::NoteOn(0, note, 100)))
+    }
+
+    fn release(note: MidiNote, at: u64) -> ((u8, Trigger), Message) {
+        ((0, note.into()), Message(at, MidiMessage::NoteOff(0, note)))
+    }
+
+    #[test]
+    fn test_chord_suppresses_pads() {
+        let mut chords = detector();
+
+        let (c, m) = press(MidiNote::C(3), 0);
+        let outcome = chords.press(c, m);
+        assert!(outcome.events.is_empty());
+        let (_, generation) = outcome.timer.unwrap();
+
+        let (c, m) = press(MidiNote::G(3), 10 * MS);
+        assert!(chords.press(c, m).events.is_empty());
+        let (c, m) = press(MidiNote::E(3), 20 * MS);
+        assert_eq!(
+            chords.press(c, m).events,
+            vec![ChordEvent::ChordPress(
+                0,
+                vec![(0, MidiNote::C(3).into()), (0, MidiNote::G(3).into()), (0, MidiNote::E(3).into())]
+            )]
+        );
+        assert!(chords.timeout(generation).is_empty());
+
+        let (c, m) = release(MidiNote::E(3), 100 * MS);
+        assert_eq!(chords.release(c, m).events, vec![ChordEvent::ChordRelease(0)]);
+        let (c, m) = release(MidiNote::C(3), 110 * MS);
+        assert!(chords.release(c, m).events.is_empty());
+    }
+
+    #[test]
+    fn test_incomplete_chord_is_passed_on() {
+        let mut chords = detector();
+
+        let (c, m) = press(MidiNote::C(3), 0);
+        let (_, generation) = chords.press(c, m).timer.unwrap();
+        assert_eq!(chords.timeout(generation), vec![ChordEvent::Press(c, m)]);
+
+        // pads outside of chords are passed on right away
+        let (c, m) = press(MidiNote::D(3), 0);
+        assert_eq!(chords.press(c, m).events, vec![ChordEvent::Press(c, m)]);
+
+        let (c, m) = press(MidiNote::E(3), 100 * MS);
+        chords.press(c, m);
+        let (_, r) = release(MidiNote::E(3), 110 * MS);
+        assert_eq!(chords.release(c, r).events, vec![ChordEvent::Press(c, m), ChordEvent::Release(c, r)]);
+    }
+}
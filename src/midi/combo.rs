@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+use crate::{
+    config::Combo,
+    midi::mapping::{Control, MappingKey},
+};
+
+/// Result of a press for all combos
+#[derive(Debug, Default, PartialEq)]
+pub struct ComboMatch {
+    /// Indices of the combos the press completed
+    pub completed: Vec<usize>,
+
+    /// The press continued a combo that holds back the actions of its steps
+    pub suppress: bool,
+}
+
+/// Follows the progress of every combo, timestamps are the microseconds of [Message](crate::midi::message::Message)
+pub struct ComboRecognizer {
+    combos: Vec<Combo>,
+
+    /// Next step of every combo and when the last one was pressed
+    progress: Vec<(usize, u64)>,
+
+    /// Per combo and step, how many steps are still matched when the press after it is wrong
+    fallbacks: Vec<Vec<usize>>,
+}
+
+/// Length of the longest proper prefix of `steps[..=i]` that is also its suffix, for every `i`
+fn fallbacks(steps: &[MappingKey]) -> Vec<usize> {
+    let mut table = vec![0; steps.len()];
+    let mut matched = 0;
+    for i in 1..steps.len() {
+        while matched > 0 && steps[i] != steps[matched] {
+            matched = table[matched - 1];
+        }
+        if steps[i] == steps[matched] {
+            matched += 1;
+        }
+        table[i] = matched;
+    }
+    table
+}
+
+impl ComboRecognizer {
+    pub fn new(combos: Vec<Combo>) -> Self {
+        Self {
+            progress: vec![(0, 0); combos.len()],
+            fallbacks: combos.iter().map(|c| fallbacks(&c.steps)).collect(),
+            combos,
+        }
+    }
+
+    pub fn press(&mut self, control: Control, at: u64) -> ComboMatch {
+        let mut result = ComboMatch::default();
+
+        let progress = self.progress.iter_mut().zip(self.fallbacks.iter());
+        for (index, (combo, ((next, last), fallbacks))) in self.combos.iter().zip(progress).enumerate() {
+            if combo.steps.is_empty() {
+                continue;
+            }
+
+            let gap = Duration::from_millis(combo.max_gap_ms).as_micros() as u64;
+            if *next > 0 && at.saturating_sub(*last) > gap {
+                *next = 0;
+            }
+
+            // a wrong press keeps the steps that still match, like DOWN DOWN DOWN for DOWN DOWN FORWARD
+            while *next > 0 && !combo.steps[*next].matches(control) {
+                *next = fallbacks[*next - 1];
+            }
+            if combo.steps[*next].matches(control) {
+                if *next > 0 && !combo.passthrough {
+                    result.suppress = true;
+                }
+                *next += 1;
+            }
+            *last = at;
+
+            if *next == combo.steps.len() {
+                *next = 0;
+                result.completed.push(index);
+            }
+        }
+
+        result
+    }
+
+    /// Forgets the progress of all combos
+    pub fn reset(&mut self) {
+        self.progress.iter_mut().for_each(|p| *p = (0, 0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ComboMatch, ComboRecognizer};
+    use crate::{
+        config::{Action, Combo},
+        midi::{
+            mapping::{MappingKey, Trigger},
+            note::MidiNote,
+        },
+    };
+
+    const MS: u64 = 1000;
+    const DOWN: MidiNote = MidiNote::F(3);
+    const FORWARD: MidiNote = MidiNote::G(3);
+    const PUNCH: MidiNote = MidiNote::C(4);
+
+    fn recognizer(passthrough: bool) -> ComboRecognizer {
+        combo(&[DOWN, FORWARD, PUNCH], passthrough)
+    }
+
+    fn combo(steps: &[MidiNote], passthrough: bool) -> ComboRecognizer {
+        ComboRecognizer::new(vec![Combo {
+            steps: steps.iter().map(|note| MappingKey { channel: None, trigger: (*note).into() }).collect(),
+            max_gap_ms: 100,
+            passthrough,
+            action: Action::Key(rdev::Key::KeyP.into()),
+        }])
+    }
+
+    fn press(combos: &mut ComboRecognizer, note: MidiNote, at: u64) -> ComboMatch {
+        combos.press((0, Trigger::Note(note)), at)
+    }
+
+    #[test]
+    fn test_combo_in_time() {
+        let mut combos = recognizer(true);
+
+        assert_eq!(press(&mut combos, DOWN, 0), ComboMatch::default());
+        assert_eq!(press(&mut combos, FORWARD, 50 * MS), ComboMatch::default());
+        assert_eq!(
+            press(&mut combos, PUNCH, 120 * MS),
+            ComboMatch { completed: vec![0], suppress: false }
+        );
+
+        // too slow
+        press(&mut combos, DOWN, 1000 * MS);
+        press(&mut combos, FORWARD, 1200 * MS);
+        assert!(press(&mut combos, PUNCH, 1250 * MS).completed.is_empty());
+
+        // a wrong press in between
+        press(&mut combos, DOWN, 2000 * MS);
+        press(&mut combos, PUNCH, 2010 * MS);
+        press(&mut combos, FORWARD, 2020 * MS);
+        assert!(press(&mut combos, PUNCH, 2030 * MS).completed.is_empty());
+    }
+
+    #[test]
+    fn test_intermediate_steps_suppressed() {
+        let mut combos = recognizer(false);
+
+        assert!(!press(&mut combos, DOWN, 0).suppress);
+        assert!(press(&mut combos, FORWARD, 10 * MS).suppress);
+        assert_eq!(
+            press(&mut combos, PUNCH, 20 * MS),
+            ComboMatch { completed: vec![0], suppress: true }
+        );
+        assert!(!press(&mut combos, FORWARD, 30 * MS).suppress);
+    }
+
+    #[test]
+    fn test_repeated_steps() {
+        let mut combos = combo(&[DOWN, DOWN, FORWARD], true);
+
+        press(&mut combos, DOWN, 0);
+        press(&mut combos, DOWN, 10 * MS);
+        press(&mut combos, DOWN, 20 * MS);
+        assert_eq!(press(&mut combos, FORWARD, 30 * MS).completed, vec![0]);
+
+        let mut combos = combo(&[DOWN, FORWARD, DOWN, PUNCH], true);
+        for (i, note) in [DOWN, FORWARD, DOWN, FORWARD, DOWN].into_iter().enumerate() {
+            assert!(press(&mut combos, note, i as u64 * 10 * MS).completed.is_empty());
+        }
+        assert_eq!(press(&mut combos, PUNCH, 50 * MS).completed, vec![0]);
+    }
+}
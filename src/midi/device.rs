@@ -1,8 +1,11 @@
 use std::{
     error::Error,
     io::{Write, stdin, stdout},
+    thread,
+    time::Duration,
 };
 
+use log::warn;
 use midir::{MidiIO, MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection};
 use tokio::sync::mpsc::Sender;
 
@@ -12,7 +15,7 @@ use crate::midi::message::Message;
 pub fn connect_input(
     name: Option<String>,
     tx: Sender<Message>,
-) -> Result<MidiInputConnection<Sender<Message>>, Box<dyn Error>> {
+) -> Result<(), Box<dyn Error>> {
     let mut midi_in = MidiInput::new("midir forwarding input")?;
     //midi_in.ignore(midir::Ignore::Time);
     midi_in.ignore(midir::Ignore::None);
@@ -23,7 +26,8 @@ pub fn connect_input(
         select_port(&midi_in, "midi input")?
     };
 
-    Ok(midi_in.connect(
+    let port_name = midi_in.port_name(&in_port)?;
+    let connection = midi_in.connect(
         &in_port,
         "lppro-gamecontroller",
         move |ts, msg, tx| {
@@ -38,7 +42,32 @@ pub fn connect_input(
             }
         },
         tx,
-    )?)
+    )?;
+
+    watch_input(port_name, connection);
+    Ok(())
+}
+
+/// Closes the connection once its port disappears, which closes the channel of the input task
+fn watch_input(port_name: String, connection: MidiInputConnection<Sender<Message>>) {
+    thread::spawn(move || {
+        loop {
+            thread::sleep(Duration::from_secs(1));
+
+            let Ok(midi_in) = MidiInput::new("midir watch input") else {
+                continue;
+            };
+            let connected = midi_in
+                .ports()
+                .iter()
+                .any(|p| midi_in.port_name(p).is_ok_and(|name| name == port_name));
+            if !connected {
+                warn!("{} disconnected", port_name);
+                connection.close();
+                return;
+            }
+        }
+    });
 }
 
 /// Connect to MIDI Output
@@ -76,7 +105,7 @@ fn select_port_by_name<T: MidiIO>(midi_io: &T, search: String) -> Result<T::Port
 
     let possible: Vec<T::Port> = midi_ports.iter().enumerate().filter(|(_, v)| {
         let name = midi_io.port_name(v).unwrap();
-        return name.trim() == search.trim();
+        name.trim() == search.trim()
     }).map(|(_, p)| {
         p.clone()
     }).collect();
@@ -0,0 +1,208 @@
+use std::{collections::HashMap, time::Duration};
+
+use crate::{
+    config::{Gestures, Timing},
+    midi::mapping::Control,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gesture {
+    Tap,
+    Hold,
+    DoubleTap,
+}
+
+/// What has to happen with the action of a gesture
+#[derive(Debug, PartialEq)]
+pub enum GestureEvent {
+    Press(Gesture),
+    Release(Gesture),
+}
+
+/// Result of feeding the tracker, a timer has to report back through [GestureTracker::timeout]
+#[derive(Debug, Default, PartialEq)]
+pub struct GestureOutcome {
+    pub events: Vec<GestureEvent>,
+    pub timer: Option<(Duration, u64)>,
+}
+
+#[derive(Debug)]
+enum PadState {
+    /// Down, not decided yet
+    Pressed { at: u64 },
+
+    /// Held past the hold time, the hold action is pressed
+    Holding,
+
+    /// Released after a tap, waiting for a second one
+    Released { at: u64 },
+
+    /// Pressed again within the window, the double tap action is pressed
+    Double,
+}
+
+/// Tells taps, holds and double taps apart, timestamps are the microseconds of [Message](crate::midi::message::Message)
+#[derive(Default)]
+pub struct GestureTracker {
+    pads: HashMap<Control, PadState>,
+
+    /// Generation of the timer each pad waits for, outdated timers are ignored
+    armed: HashMap<Control, u64>,
+    generation: u64,
+}
+
+fn tap() -> Vec<GestureEvent> {
+    vec![
+        GestureEvent::Press(Gesture::Tap),
+        GestureEvent::Release(Gesture::Tap),
+    ]
+}
+
+impl GestureTracker {
+    fn timer(&mut self, control: Control, duration: Duration) -> Option<(Duration, u64)> {
+        self.generation += 1;
+        self.armed.insert(control, self.generation);
+        Some((duration, self.generation))
+    }
+
+    pub fn press(&mut self, control: Control, gestures: &Gestures, timing: &Timing, at: u64) -> GestureOutcome {
+        let mut outcome = GestureOutcome::default();
+        self.armed.remove(&control);
+
+        if let Some(PadState::Released { at: released }) = self.pads.remove(&control) {
+            if at.saturating_sub(released) <= gestures.double_tap_time(timing).as_micros() as u64 {
+                self.pads.insert(control, PadState::Double);
+                outcome.events.push(GestureEvent::Press(Gesture::DoubleTap));
+                return outcome;
+            }
+
+            // the window passed before its timer was handled
+            outcome.events.extend(tap());
+        }
+
+        self.pads.insert(control, PadState::Pressed { at });
+        if gestures.hold.is_some() {
+            outcome.timer = self.timer(control, gestures.hold_time(timing));
+        }
+        outcome
+    }
+
+    pub fn release(&mut self, control: Control, gestures: &Gestures, timing: &Timing, at: u64) -> GestureOutcome {
+        let mut outcome = GestureOutcome::default();
+        self.armed.remove(&control);
+
+        match self.pads.remove(&control) {
+            Some(PadState::Pressed { at: pressed }) => {
+                let held = at.saturating_sub(pressed);
+                if gestures.hold.is_some() && held >= gestures.hold_time(timing).as_micros() as u64 {
+                    // held long enough, but released before the timer was handled
+                    outcome.events.push(GestureEvent::Press(Gesture::Hold));
+                    outcome.events.push(GestureEvent::Release(Gesture::Hold));
+                } else if gestures.double_tap.is_some() {
+                    self.pads.insert(control, PadState::Released { at });
+                    outcome.timer = self.timer(control, gestures.double_tap_time(timing));
+                } else {
+                    outcome.events.extend(tap());
+                }
+            }
+            Some(PadState::Holding) => outcome.events.push(GestureEvent::Release(Gesture::Hold)),
+            Some(PadState::Double) => outcome.events.push(GestureEvent::Release(Gesture::DoubleTap)),
+            Some(state @ PadState::Released { .. }) => {
+                self.pads.insert(control, state);
+            }
+            None => {}
+        }
+
+        outcome
+    }
+
+    /// A timer armed by an earlier outcome ran out
+    pub fn timeout(&mut self, control: Control, generation: u64) -> Vec<GestureEvent> {
+        if self.armed.get(&control) != Some(&generation) {
+            return vec![];
+        }
+        self.armed.remove(&control);
+
+        match self.pads.remove(&control) {
+            Some(PadState::Pressed { .. }) => {
+                self.pads.insert(control, PadState::Holding);
+                vec![GestureEvent::Press(Gesture::Hold)]
+            }
+            Some(PadState::Released { .. }) => tap(),
+            Some(state) => {
+                self.pads.insert(control, state);
+                vec![]
+            }
+            None => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Gesture, GestureEvent, GestureTracker, tap};
+    use crate::{
+        config::{Action, Gestures, Timing},
+        midi::{mapping::Trigger, note::MidiNote},
+    };
+
+    const MS: u64 = 1000;
+
+    fn gestures(hold: bool, double_tap: bool) -> Gestures {
+        let action = || Some(Box::new(Action::Key(rdev::Key::KeyA.into())));
+        Gestures {
+            tap: action(),
+            hold: if hold { action() } else { None },
+            double_tap: if double_tap { action() } else { None },
+            hold_ms: None,
+            double_tap_ms: Some(200),
+        }
+    }
+
+    #[test]
+    fn test_tap_and_hold() {
+        let control = (0, Trigger::Note(MidiNote::C(4)));
+        let (gestures, timing) = (gestures(true, false), Timing::default());
+        let mut tracker = GestureTracker::default();
+
+        let outcome = tracker.press(control, &gestures, &timing, 0);
+        assert!(outcome.events.is_empty());
+        let (_, generation) = outcome.timer.unwrap();
+        assert_eq!(tracker.release(control, &gestures, &timing, 100 * MS).events, tap());
+        assert!(tracker.timeout(control, generation).is_empty());
+
+        let (_, generation) = tracker.press(control, &gestures, &timing, 1000 * MS).timer.unwrap();
+        assert_eq!(tracker.timeout(control, generation), vec![GestureEvent::Press(Gesture::Hold)]);
+        assert_eq!(
+            tracker.release(control, &gestures, &timing, 2000 * MS).events,
+            vec![GestureEvent::Release(Gesture::Hold)]
+        );
+    }
+
+    #[test]
+    fn test_double_tap() {
+        let control = (0, Trigger::Note(MidiNote::C(4)));
+        let (gestures, timing) = (gestures(false, true), Timing::default());
+        let mut tracker = GestureTracker::default();
+
+        tracker.press(control, &gestures, &timing, 0);
+        let outcome = tracker.release(control, &gestures, &timing, 50 * MS);
+        let (_, generation) = outcome.timer.unwrap();
+        assert!(outcome.events.is_empty());
+
+        assert_eq!(
+            tracker.press(control, &gestures, &timing, 150 * MS).events,
+            vec![GestureEvent::Press(Gesture::DoubleTap)]
+        );
+        assert!(tracker.timeout(control, generation).is_empty());
+        assert_eq!(
+            tracker.release(control, &gestures, &timing, 200 * MS).events,
+            vec![GestureEvent::Release(Gesture::DoubleTap)]
+        );
+
+        // a single tap fires once the window closed
+        tracker.press(control, &gestures, &timing, 1000 * MS);
+        let (_, generation) = tracker.release(control, &gestures, &timing, 1050 * MS).timer.unwrap();
+        assert_eq!(tracker.timeout(control, generation), tap());
+    }
+}
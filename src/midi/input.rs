@@ -1,24 +1,65 @@
-use std::sync::Arc;
-
-use log::{debug, info, trace};
-use tokio::{
-    sync::{Mutex, broadcast, mpsc},
-    task::JoinHandle,
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
 };
 
+use log::{debug, info, trace, warn};
+use tokio::sync::{Mutex, broadcast, mpsc};
+
 use crate::{
     DeviceNameRetrieve,
-    config::{Action, Config},
-    midi::message::{Message, MidiMessage},
-    virtual_input::InputBackend,
+    config::{Action, Binding, CommandOutput, KeyChord, Modifier, RadioGroup, Retrigger, Sequence, SocdGroup, Step, TextSettings, Timing, Turbo as TurboConfig, TurboScale, VelocityCurve},
+    midi::{
+        chord::{ChordDetector, ChordEvent},
+        combo::ComboRecognizer,
+        gesture::{Gesture, GestureEvent, GestureOutcome, GestureTracker},
+        mapping::{Control, MAPPING, MappingKey, Trigger, lookup},
+        message::{Message, MidiMessage, MidiValue},
+        output::{Light, OverlayEvent},
+        pressure::{Pad, PressureChange, PressureTracker},
+        radio::{RadioSelector, Selection},
+        socd::{SocdChange, SocdResolver},
+    },
+    virtual_input::{
+        ControlValue, InputBackend,
+        sequence::{self, RunningSequence},
+        shell::{self, CommandStatus, RunningCommand, Supervision},
+        turbo::Turbo,
+    },
 };
 
 pub enum InputTaskError {}
 
+/// How long the exit status of a command is shown on its pad
+const EXIT_LIGHT_TIME: Duration = Duration::from_secs(1);
+
+/// Timers report back to the input task with these
+#[derive(Debug)]
+enum Timer {
+    /// Carries the binding of the press, the layers might have changed until it runs out
+    Gesture(Control, u64, Box<Binding>),
+    Chord(u64),
+}
+
+pub trait InputSettingsRetrieve: DeviceNameRetrieve {
+    fn get_timing(&self) -> Timing;
+    fn get_panic_combo(&self) -> Vec<MappingKey>;
+    fn get_pause_combo(&self) -> Vec<MappingKey>;
+    fn get_socd_groups(&self) -> Vec<SocdGroup>;
+    fn get_radio_groups(&self) -> Vec<RadioGroup>;
+    fn get_velocity_curve(&self) -> VelocityCurve;
+    fn get_text_settings(&self) -> TextSettings;
+}
+
+#[allow(dead_code)]
 pub async fn daw_mode_task(
     mut from_raw_device: mpsc::Receiver<Message>,
-    backend: Arc<Mutex<Box<dyn InputBackend>>>,
-    internal_broadcast: broadcast::Sender<MidiMessage>,
+    _backend: Arc<Mutex<Box<dyn InputBackend>>>,
+    _internal_broadcast: broadcast::Sender<OverlayEvent>,
     mut cancellation: broadcast::Receiver<()>,
 ) -> Result<(), InputTaskError> {
     loop {
@@ -35,51 +76,73 @@ pub async fn daw_mode_task(
     Ok(())
 }
 
-pub async fn input_task(
+pub async fn input_task<C>(
+    config: C,
     mut from_raw_device: mpsc::Receiver<Message>,
     backend: Arc<Mutex<Box<dyn InputBackend>>>,
-    internal_broadcast: broadcast::Sender<MidiMessage>,
+    internal_broadcast: broadcast::Sender<OverlayEvent>,
+    mut pause_requests: mpsc::Receiver<()>,
     mut cancellation: broadcast::Receiver<()>,
-) -> Result<(), InputTaskError> {
+) -> Result<(), InputTaskError>
+where
+    C: InputSettingsRetrieve,
+{
+    // gesture and chord timers report back here
+    let (timer_tx, mut timers) = mpsc::channel(100);
+    let mut dispatcher = Dispatcher::new(&config, backend, internal_broadcast, timer_tx);
+    let curve = config.get_velocity_curve();
+
     //tokio::spawn(async move {
     loop {
         tokio::select! {
             msg = from_raw_device.recv() => {
-                let _msg_clone = msg.clone();
                 match msg {
-                    Some(msg) => {
+                    Some(mut msg) => {
+                        trace!("[{}] {:?}", msg.0, msg.1);
+                        if let MidiMessage::NoteOn(ch, note, vel) = msg.1 {
+                            msg.1 = MidiMessage::NoteOn(ch, note, curve.apply(vel));
+                        }
+
                         match msg.1 {
-                            MidiMessage::NoteOn(_ch, note, _vel) => {
+                            MidiMessage::NoteOn(ch, note, _vel) => {
                                 trace!("{:?}", note);
-                                let action: Option<Action> = note.into();
-                                if let Some(action) = action {
-
-                                    let mut lock = backend.lock().await;
-                                    lock.process_on_action(action);
-                                    drop(lock);
-
-                                    // send to overlay
-                                    internal_broadcast.send(msg.1).unwrap();
-                                }
+                                dispatcher.press((ch, note.into()), msg).await;
                             },
-                            MidiMessage::NoteOff(_ch, note) => {
+                            MidiMessage::NoteOff(ch, note) => {
                                 trace!("{:?}", note);
-                                let action: Option<Action> = note.into();
-                                if let Some(action) = action {
-
-                                    let mut lock = backend.lock().await;
-                                    lock.process_off_action(action);
-                                    drop(lock);
-
-                                    internal_broadcast.send(msg.1).unwrap();
+                                dispatcher.release((ch, note.into()), msg).await;
+                            },
+                            MidiMessage::AfterTouch(ch, note, vel) => {
+                                trace!("{:?} pressure {}", note, vel);
+                                dispatcher.apply_pressure((ch, note), vel).await;
+                            },
+                            MidiMessage::ChannelPressure(ch, val) => {
+                                trace!("channel {} pressure {}", ch, val);
+                                for note in dispatcher.pressure.held_on(ch) {
+                                    dispatcher.apply_pressure((ch, note), val).await;
                                 }
                             },
-                            MidiMessage::AfterTouch(_ch, note, _vel) => {
-                                trace!("{:?}", note);
-                                let action: Option<Action> = note.into();
+                            MidiMessage::ControlChange(ch, cc, val) => {
+                                trace!("CC {} = {}", cc, val);
+                                let trigger = Trigger::ControlChange(cc);
+                                let binding = lookup(ch, trigger);
+                                if let Some(binding) = binding.filter(|b| b.action.is_continuous()) {
+                                    dispatcher.apply_value((ch, trigger), binding.action, val as f64 / 127.0).await;
+                                    continue;
+                                }
 
-                                if let Some(_action) = action {
-                                    // todo
+                                // momentary buttons send 127 on press and 0 on release
+                                if val > 0 {
+                                    dispatcher.press((ch, trigger), msg).await;
+                                } else {
+                                    dispatcher.release((ch, trigger), msg).await;
+                                }
+                            },
+                            MidiMessage::PitchBend(ch, pitch) => {
+                                trace!("PitchBend {}", pitch);
+                                let binding = lookup(ch, Trigger::PitchBend);
+                                if let Some(binding) = binding.filter(|b| b.action.is_continuous()) {
+                                    dispatcher.apply_value((ch, Trigger::PitchBend), binding.action, pitch as f64 / 16383.0).await;
                                 }
                             },
                             MidiMessage::Unknown => {
@@ -94,17 +157,809 @@ pub async fn input_task(
                         }
                     },
                     None => {
-                        // channel has been closed
+                        // channel has been closed, the device is gone
+                        warn!("input channel closed");
                         break;
                     },
                 }
             }
+            Some(()) = pause_requests.recv() => {
+                dispatcher.toggle_pause().await;
+            }
+            Some(timer) = timers.recv() => {
+                match timer {
+                    Timer::Gesture(control, generation, binding) => dispatcher.timeout(control, generation, &binding).await,
+                    Timer::Chord(generation) => {
+                        let events = dispatcher.chords.timeout(generation);
+                        dispatcher.apply_chords(events).await;
+                    }
+                }
+            }
             _c = cancellation.recv() => {
                 debug!("closing input task");
                 break;
             }
         }
     }
+
+    dispatcher.shutdown().await;
     Ok(())
     //})
 }
+
+/// Resolves triggers to bindings and hands their actions to the backend
+struct Dispatcher {
+    backend: Arc<Mutex<Box<dyn InputBackend>>>,
+    overlay: broadcast::Sender<OverlayEvent>,
+    timing: Timing,
+
+    /// How text actions and sequences are typed
+    text: TextSettings,
+
+    /// Every trigger that is down, mapped or not
+    down: HashSet<Control>,
+
+    /// Bindings of pressed triggers, releases use these even if the layers changed in between
+    held: HashMap<Control, Binding>,
+
+    /// Triggers that release everything when held together
+    panic: Vec<MappingKey>,
+
+    /// Triggers that suspend or resume dispatching when held together
+    pause: Vec<MappingKey>,
+    paused: bool,
+
+    /// last normalized value of every continuous control
+    values: HashMap<Control, f64>,
+
+    pressure: PressureTracker,
+
+    /// Sequences started by a trigger, they keep running after its release
+    sequences: HashMap<Control, RunningSequence>,
+
+    gestures: GestureTracker,
+    timers: mpsc::Sender<Timer>,
+
+    chords: ChordDetector,
+    chord_bindings: Vec<Binding>,
+
+    /// Pressed chords and the pad their action was activated for
+    held_chords: HashMap<usize, Control>,
+
+    combos: ComboRecognizer,
+    combo_actions: Vec<Action>,
+
+    /// Completed combos, their actions are held until the last step is released
+    held_combos: HashMap<Control, Vec<usize>>,
+
+    /// Pads that were part of a combo instead of sending their own action
+    suppressed: HashSet<Control>,
+
+    /// Held shift modifier pads
+    shift: HashSet<Control>,
+
+    /// Press commands of shell actions per pad
+    commands: HashMap<Control, Vec<RunningCommand>>,
+
+    /// Release commands, they are not tied to a pad
+    detached: Vec<RunningCommand>,
+
+    /// Commands started per pad, only the latest one shows its status
+    command_runs: HashMap<Control, Arc<AtomicU64>>,
+
+    /// Keys repeating while their trigger is held
+    turbos: HashMap<Control, Turbo>,
+
+    /// Actions kept active after their trigger was released
+    latched: HashMap<Control, Action>,
+
+    socd: SocdResolver,
+    radio: RadioSelector,
+}
+
+impl Dispatcher {
+    fn new<C: InputSettingsRetrieve>(
+        config: &C,
+        backend: Arc<Mutex<Box<dyn InputBackend>>>,
+        overlay: broadcast::Sender<OverlayEvent>,
+        timers: mpsc::Sender<Timer>,
+    ) -> Self {
+        let timing = config.get_timing();
+        let (chords, chord_bindings) = MAPPING.lock().unwrap().chords().into_iter().unzip();
+        let combos = MAPPING.lock().unwrap().combos();
+
+        Self {
+            backend,
+            overlay,
+            chords: ChordDetector::new(chords, Duration::from_millis(timing.chord_ms)),
+            chord_bindings,
+            held_chords: HashMap::new(),
+            combo_actions: combos.iter().map(|c| c.action.clone()).collect(),
+            combos: ComboRecognizer::new(combos),
+            held_combos: HashMap::new(),
+            suppressed: HashSet::new(),
+            shift: HashSet::new(),
+            commands: HashMap::new(),
+            detached: Vec::new(),
+            command_runs: HashMap::new(),
+            timing,
+            text: config.get_text_settings(),
+            down: HashSet::new(),
+            held: HashMap::new(),
+            panic: config.get_panic_combo(),
+            pause: config.get_pause_combo(),
+            paused: false,
+            values: HashMap::new(),
+            pressure: PressureTracker::default(),
+            sequences: HashMap::new(),
+            gestures: GestureTracker::default(),
+            timers,
+            turbos: HashMap::new(),
+            latched: HashMap::new(),
+            socd: SocdResolver::new(config.get_socd_groups()),
+            radio: RadioSelector::new(config.get_radio_groups()),
+        }
+    }
+
+    async fn press(&mut self, control: Control, msg: Message) {
+        // some devices repeat NoteOn without a NoteOff in between
+        if !self.down.insert(control) {
+            trace!("{:?} is already down", control);
+            return;
+        }
+
+        if self.combo_down(&self.panic) {
+            warn!("panic combo pressed, releasing everything");
+            self.release_everything().await;
+            self.notify(OverlayEvent::Redraw);
+            return;
+        }
+
+        if self.combo_down(&self.pause) {
+            self.toggle_pause().await;
+            return;
+        }
+
+        if self.paused {
+            return;
+        }
+
+        let outcome = self.chords.press(control, msg);
+        if let Some((duration, generation)) = outcome.timer {
+            self.arm_timer(Timer::Chord(generation), duration);
+        }
+        self.apply_chords(outcome.events).await;
+    }
+
+    async fn release(&mut self, control: Control, msg: Message) {
+        self.down.remove(&control);
+        if self.paused {
+            return;
+        }
+
+        let outcome = self.chords.release(control, msg);
+        // pads of a chord are not released on their own
+        if !outcome.events.contains(&ChordEvent::Release(control, msg)) {
+            self.notify(OverlayEvent::Light(control.1, Light::Idle));
+        }
+        self.apply_chords(outcome.events).await;
+    }
+
+    /// Whether all pads of a non empty combo are down
+    fn combo_down(&self, keys: &[MappingKey]) -> bool {
+        !keys.is_empty() && keys.iter().all(|key| self.down.iter().any(|c| key.matches(*c)))
+    }
+
+    /// Suspends or resumes dispatching actions, everything held is released when pausing
+    async fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        if self.paused {
+            info!("paused");
+            self.release_everything().await;
+        } else {
+            info!("resumed");
+        }
+        self.notify(OverlayEvent::Paused(self.paused));
+    }
+
+    /// Hands the presses the chord detector let through to the pads and handles completed chords
+    async fn apply_chords(&mut self, events: Vec<ChordEvent>) {
+        for event in events {
+            match event {
+                ChordEvent::Press(control, msg) => self.press_pad(control, msg).await,
+                ChordEvent::Release(control, msg) => self.release_pad(control, msg).await,
+                ChordEvent::ChordPress(index, pads) => {
+                    let action = self.chord_bindings[index].action.clone();
+                    self.activate(pads[0], &action).await;
+                    self.held_chords.insert(index, pads[0]);
+                    for pad in pads {
+                        self.notify(OverlayEvent::Light(pad.1, Light::Active));
+                    }
+                }
+                ChordEvent::ChordRelease(index) => {
+                    if let Some(control) = self.held_chords.remove(&index) {
+                        let action = self.chord_bindings[index].action.clone();
+                        self.deactivate(control, &action).await;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn press_pad(&mut self, control: Control, msg: Message) {
+        let binding = lookup(control.0, control.1);
+        let velocity = msg.1.velocity();
+        if let Some(min) = binding.as_ref().and_then(|b| b.min_velocity)
+            && velocity < min
+        {
+            debug!("ignoring {:?}, velocity {} is below {}", control, velocity, min);
+            return;
+        }
+
+        let combo = self.combos.press(control, msg.0);
+        for index in combo.completed.iter() {
+            debug!("combo {} completed", index);
+            let action = self.combo_actions[*index].clone();
+            self.activate(control, &action).await;
+        }
+        if !combo.completed.is_empty() {
+            self.held_combos.insert(control, combo.completed);
+        }
+        if combo.suppress {
+            self.suppressed.insert(control);
+            self.notify(OverlayEvent::Pad(msg.1));
+            return;
+        }
+
+        // the release has to use the action of the zone that was hit and the shift state of the press
+        let shifted = !self.shift.is_empty();
+        let Some(binding) = binding.map(|b| b.for_velocity(velocity)) else {
+            return;
+        };
+        let binding = if shifted { binding.shifted() } else { binding };
+
+        if !matches!(binding.action, Action::Layer(_) | Action::Modifier(_)) && MAPPING.lock().unwrap().consume_oneshot() {
+            self.notify(OverlayEvent::Redraw);
+        }
+
+        if let (ch, Trigger::Note(note)) = control {
+            self.pressure.press((ch, note));
+        }
+
+        let hold = match self.radio.select(control) {
+            Some(selection) => {
+                self.change_selection(control, &binding.action, selection).await;
+                selection.hold
+            }
+            None => false,
+        };
+
+        if hold {
+            // activated by the selection, released by selecting another pad
+        } else if binding.latch {
+            self.toggle_latch(control, &binding.action).await;
+        } else if let (Some(turbo), Action::Key(chord)) = (&binding.turbo, &binding.action) {
+            self.start_turbo(control, turbo, chord.clone(), msg.1).await;
+        } else if let Action::Gesture(gestures) = &binding.action {
+            let outcome = self.gestures.press(control, gestures, &self.timing, msg.0);
+            self.apply_gestures(control, &binding, outcome).await;
+        } else if let Some(changes) = self.socd.press(control) {
+            self.held.insert(control, binding.clone());
+            // the overlay shows the direction in effect instead of the pressed pad
+            self.apply_socd(control, &binding, changes).await;
+            return;
+        } else {
+            self.activate(control, &binding.action).await;
+        }
+
+        self.held.insert(control, binding);
+
+        // send to overlay
+        self.notify(OverlayEvent::Pad(msg.1));
+    }
+
+    async fn release_pad(&mut self, control: Control, msg: Message) {
+        for index in self.held_combos.remove(&control).unwrap_or_default() {
+            let action = self.combo_actions[index].clone();
+            self.deactivate(control, &action).await;
+        }
+        if self.suppressed.remove(&control) {
+            self.notify(OverlayEvent::Pad(msg.1));
+            return;
+        }
+
+        let Some(binding) = self.held.remove(&control) else {
+            return;
+        };
+
+        let engaged = match control {
+            (ch, Trigger::Note(note)) => self.pressure.release((ch, note)),
+            _ => false,
+        };
+        if let (true, Some(p)) = (engaged, &binding.pressure) {
+            self.deactivate(control, &p.action).await;
+        }
+
+        if binding.latch || self.latched.contains_key(&control) {
+            // latched actions are released by the next press or selection
+        } else if let Some(turbo) = self.turbos.remove(&control) {
+            turbo.stop().await;
+        } else if let Action::Gesture(gestures) = &binding.action {
+            let outcome = self.gestures.release(control, gestures, &self.timing, msg.0);
+            self.apply_gestures(control, &binding, outcome).await;
+        } else if let Some(changes) = self.socd.release(control) {
+            self.apply_socd(control, &binding, changes).await;
+            return;
+        } else {
+            self.deactivate(control, &binding.action).await;
+        }
+
+        if self.radio.is_selected(control) {
+            self.notify(OverlayEvent::Light(control.1, Light::Selected));
+        } else if self.latched.contains_key(&control) {
+            self.notify(OverlayEvent::Light(control.1, Light::Latched));
+        } else {
+            self.notify(OverlayEvent::Pad(msg.1));
+        }
+    }
+
+    /// Deselects the previous pad of a radio group, holding groups swap the active action
+    async fn change_selection(&mut self, control: Control, action: &Action, selection: Selection) {
+        if selection.previous == Some(control) {
+            return;
+        }
+
+        if let Some(previous) = selection.previous {
+            if let Some(action) = self.latched.remove(&previous) {
+                self.deactivate(previous, &action).await;
+            }
+            self.notify(OverlayEvent::Light(previous.1, Light::Idle));
+        }
+
+        if selection.hold {
+            self.activate(control, action).await;
+            self.latched.insert(control, action.clone());
+        }
+    }
+
+    /// Switches the actions of a direction group, `binding` belongs to `control` which might not be held anymore
+    async fn apply_socd(&mut self, control: Control, binding: &Binding, changes: Vec<SocdChange>) {
+        for change in changes {
+            let (target, active) = match change {
+                SocdChange::Activate(target) => (target, true),
+                SocdChange::Deactivate(target) => (target, false),
+            };
+            let action = if target == control {
+                binding.action.clone()
+            } else if let Some(held) = self.held.get(&target) {
+                held.action.clone()
+            } else {
+                continue;
+            };
+
+            if active {
+                self.activate(target, &action).await;
+                self.notify(OverlayEvent::Light(target.1, Light::Active));
+            } else {
+                self.deactivate(target, &action).await;
+                self.notify(OverlayEvent::Light(target.1, Light::Idle));
+            }
+        }
+    }
+
+    /// First press activates the action and keeps it, the next one deactivates it again
+    async fn toggle_latch(&mut self, control: Control, action: &Action) {
+        if let Some(action) = self.latched.remove(&control) {
+            self.deactivate(control, &action).await;
+            self.notify(OverlayEvent::Light(control.1, Light::Idle));
+        } else {
+            self.activate(control, action).await;
+            self.latched.insert(control, action.clone());
+        }
+    }
+
+    async fn start_turbo(&mut self, control: Control, turbo: &TurboConfig, chord: KeyChord, msg: MidiMessage) {
+        let velocity = msg.velocity();
+        let rate = match turbo.scale {
+            TurboScale::Velocity => turbo.rate(velocity),
+            // no pressure was reported yet
+            TurboScale::Pressure => turbo.rate(0),
+            TurboScale::Fixed => turbo.rate,
+        };
+
+        let overlay = self.overlay.clone();
+        let blink = move |on| {
+            let light = if on { Light::Active } else { Light::Idle };
+            let _ = overlay.send(OverlayEvent::Light(control.1, light));
+        };
+        let running = Turbo::start(self.backend.clone(), chord, rate, blink);
+        if let Some(previous) = self.turbos.insert(control, running) {
+            previous.stop().await;
+        }
+    }
+
+    /// Releases everything and terminates all commands before the task exits, so nothing is left behind on the host
+    async fn shutdown(&mut self) {
+        self.release_everything().await;
+
+        for mut command in self.commands.drain().flat_map(|(_, c)| c).chain(self.detached.drain(..)) {
+            command.terminate();
+            command.join().await;
+        }
+    }
+
+    /// Stops sequences, turbos and latches and releases all keys, held pads are forgotten until they are pressed again.
+    /// Commands keep running unless they belong to a held pad.
+    async fn release_everything(&mut self) {
+        // dropping cancels them
+        self.sequences.clear();
+        for (_, turbo) in self.turbos.drain() {
+            turbo.stop().await;
+        }
+        for (control, action) in std::mem::take(&mut self.latched) {
+            debug!("releasing latched {:?}", control);
+            self.deactivate(control, &action).await;
+            self.notify(OverlayEvent::Light(control.1, Light::Idle));
+        }
+        self.socd.reset();
+        self.chords.reset();
+        self.combos.reset();
+        self.suppressed.clear();
+        for (control, indices) in std::mem::take(&mut self.held_combos) {
+            for index in indices {
+                let action = self.combo_actions[index].clone();
+                self.deactivate(control, &action).await;
+            }
+        }
+        for (index, control) in std::mem::take(&mut self.held_chords) {
+            let action = self.chord_bindings[index].action.clone();
+            self.deactivate(control, &action).await;
+        }
+        for control in self.radio.reset() {
+            self.notify(OverlayEvent::Light(control.1, Light::Idle));
+        }
+        for (control, binding) in std::mem::take(&mut self.held) {
+            if let (ch, Trigger::Note(note)) = control {
+                self.pressure.release((ch, note));
+            }
+            if !binding.latch {
+                self.deactivate(control, &binding.action).await;
+            }
+        }
+
+        self.backend.lock().await.release_all();
+    }
+
+    /// A gesture timer ran out, the pad might still be held or wait for a second tap
+    async fn timeout(&mut self, control: Control, generation: u64, binding: &Binding) {
+        if self.paused {
+            return;
+        }
+        let events = self.gestures.timeout(control, generation);
+        if events.is_empty() {
+            return;
+        }
+
+        let outcome = GestureOutcome { events, timer: None };
+        self.apply_gestures(control, binding, outcome).await;
+    }
+
+    async fn apply_gestures(&mut self, control: Control, binding: &Binding, outcome: GestureOutcome) {
+        let Action::Gesture(gestures) = &binding.action else {
+            return;
+        };
+
+        for event in outcome.events {
+            let (gesture, pressed) = match event {
+                GestureEvent::Press(gesture) => (gesture, true),
+                GestureEvent::Release(gesture) => (gesture, false),
+            };
+            let action = match gesture {
+                Gesture::Tap => &gestures.tap,
+                Gesture::Hold => &gestures.hold,
+                Gesture::DoubleTap => &gestures.double_tap,
+            };
+
+            match (action, pressed) {
+                (Some(action), true) => self.activate(control, action).await,
+                (Some(action), false) => self.deactivate(control, action).await,
+                (None, _) => {}
+            }
+        }
+
+        if let Some((duration, generation)) = outcome.timer {
+            self.arm_timer(Timer::Gesture(control, generation, Box::new(binding.clone())), duration);
+        }
+    }
+
+    fn arm_timer(&self, timer: Timer, duration: Duration) {
+        let timers = self.timers.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(duration).await;
+            let _ = timers.send(timer).await;
+        });
+    }
+
+    /// Starts an action of a pressed trigger
+    async fn activate(&mut self, control: Control, action: &Action) {
+        match action {
+            Action::Layer(switch) => {
+                if MAPPING.lock().unwrap().switch(switch, true) {
+                    self.notify(OverlayEvent::Redraw);
+                }
+            }
+            Action::Sequence(seq) => {
+                let previous = self.sequences.remove(&control);
+                let running = sequence::start(self.backend.clone(), seq.clone(), self.text.clone(), previous);
+                self.sequences.insert(control, running);
+            }
+            Action::Text(string) => {
+                let typing = Sequence {
+                    steps: vec![Step::Text(string.clone())],
+                    retrigger: Retrigger::Ignore,
+                };
+                let previous = self.sequences.remove(&control);
+                let running = sequence::start(self.backend.clone(), typing, self.text.clone(), previous);
+                self.sequences.insert(control, running);
+            }
+            Action::Shell { press: Some(press), single_instance, timeout_ms, output, .. } => {
+                let running = self.commands.entry(control).or_default();
+                running.retain(|c| !c.is_finished());
+                if *single_instance && !running.is_empty() {
+                    debug!("{:?} is still running", press);
+                    return;
+                }
+
+                // only a command that is started takes over the light of the pad
+                let on_status = self.command_light(control);
+                if let Some(command) = shell::start(press.options(), supervision(control, *timeout_ms, output), on_status) {
+                    self.commands.entry(control).or_default().push(command);
+                }
+            }
+            Action::Shell { press: None, .. } => {}
+            Action::Modifier(Modifier::Shift) => {
+                if self.shift.insert(control) && self.shift.len() == 1 {
+                    self.notify(OverlayEvent::Shift(true));
+                }
+            }
+            _ => self.backend.lock().await.process_on_action(action.clone()),
+        }
+    }
+
+    /// Shows the status of a command on the pad that started it, the exit status only briefly.
+    /// Once the pad started a newer command, the light belongs to that one.
+    fn command_light(&mut self, control: Control) -> impl Fn(CommandStatus) + Send + 'static {
+        let overlay = self.overlay.clone();
+        let runs = self.command_runs.entry(control).or_default().clone();
+        let run = runs.fetch_add(1, Ordering::SeqCst) + 1;
+
+        move |status| {
+            if runs.load(Ordering::SeqCst) != run {
+                return;
+            }
+
+            let light = match status {
+                CommandStatus::Running => Light::Running,
+                CommandStatus::Exited(true) => Light::Succeeded,
+                CommandStatus::Exited(false) => Light::Failed,
+            };
+            let _ = overlay.send(OverlayEvent::Light(control.1, light));
+
+            if let CommandStatus::Exited(_) = status {
+                let overlay = overlay.clone();
+                let runs = runs.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(EXIT_LIGHT_TIME).await;
+                    if runs.load(Ordering::SeqCst) == run {
+                        let _ = overlay.send(OverlayEvent::Light(control.1, Light::Idle));
+                    }
+                });
+            }
+        }
+    }
+
+    /// Stops an action of a released trigger
+    async fn deactivate(&mut self, control: Control, action: &Action) {
+        match action {
+            Action::Layer(switch) => {
+                if MAPPING.lock().unwrap().switch(switch, false) {
+                    self.notify(OverlayEvent::Redraw);
+                }
+            }
+            // sequences and text keep running after the release
+            Action::Sequence(_) | Action::Text(_) => {}
+            Action::Shell { release, hold, timeout_ms, output, .. } => {
+                if *hold {
+                    for command in self.commands.get_mut(&control).into_iter().flatten() {
+                        command.terminate();
+                    }
+                }
+
+                self.detached.retain(|c| !c.is_finished());
+                if let Some(release) = release {
+                    let on_status = self.command_light(control);
+                    if let Some(command) = shell::start(release.options(), supervision(control, *timeout_ms, output), on_status) {
+                        self.detached.push(command);
+                    }
+                }
+            }
+            Action::Modifier(Modifier::Shift) => {
+                if self.shift.remove(&control) && self.shift.is_empty() {
+                    self.notify(OverlayEvent::Shift(false));
+                }
+            }
+            _ => self.backend.lock().await.process_off_action(action.clone()),
+        }
+    }
+
+    /// Remembers the new value of a control and hands it to the backend together with the previous one
+    async fn apply_value(&mut self, control: Control, action: Action, value: f64) {
+        let previous = self.values.insert(control, value);
+        if self.paused {
+            return;
+        }
+        let value = ControlValue { value, previous };
+
+        self.backend.lock().await.process_value_action(action, value);
+    }
+
+    /// Presses or releases the pressure action of a held pad
+    async fn apply_pressure(&mut self, pad: Pad, value: MidiValue) {
+        if self.paused {
+            return;
+        }
+        let control = (pad.0, pad.1.into());
+        let held = self.held.get(&control);
+
+        if let (Some(turbo), Some(running)) = (held.and_then(|b| b.turbo.as_ref()), self.turbos.get(&control))
+            && turbo.scale == TurboScale::Pressure
+        {
+            running.set_rate(turbo.rate(value));
+        }
+
+        let Some(p) = held.and_then(|b| b.pressure.clone()) else {
+            return;
+        };
+
+        match self.pressure.update(pad, &p, value) {
+            Some(PressureChange::Engage) => self.activate(control, &p.action).await,
+            Some(PressureChange::Release) => self.deactivate(control, &p.action).await,
+            None => {}
+        }
+    }
+
+    fn notify(&self, event: OverlayEvent) {
+        // nobody listens if the overlay is disabled
+        let _ = self.overlay.send(event);
+    }
+}
+
+/// How the commands of a shell action started by the control are run
+fn supervision(control: Control, timeout_ms: Option<u64>, output: &CommandOutput) -> Supervision {
+    Supervision {
+        pad: control.1.to_string(),
+        timeout: timeout_ms.map(Duration::from_millis),
+        output: output.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rdev::Key;
+    use tokio::sync::{broadcast, mpsc};
+
+    use super::{Dispatcher, EXIT_LIGHT_TIME};
+    use crate::{
+        config::{Action, CommandLine, CommandOutput, DeviceConfig, ShellCommand},
+        midi::{
+            mapping::Trigger,
+            note::MidiNote,
+            output::{Light, OverlayEvent},
+        },
+        virtual_input::{recorder::recorder, shell::CommandStatus},
+    };
+
+    fn dispatcher() -> (Dispatcher, broadcast::Receiver<OverlayEvent>) {
+        let config: DeviceConfig = toml::from_str("lights = true").unwrap();
+        let (backend, _) = recorder();
+        let (overlay, events) = broadcast::channel(16);
+        let (timers, _) = mpsc::channel(16);
+
+        (Dispatcher::new(&config, backend, overlay, timers), events)
+    }
+
+    fn drain(events: &mut broadcast::Receiver<OverlayEvent>) -> Vec<OverlayEvent> {
+        std::iter::from_fn(|| events.try_recv().ok()).collect()
+    }
+
+    /// A shell action with only a press command
+    fn shell(press: &str, single_instance: bool) -> Action {
+        Action::Shell {
+            press: Some(Box::new(ShellCommand::Line(CommandLine::Shell(press.to_string())))),
+            release: None,
+            hold: false,
+            single_instance,
+            timeout_ms: None,
+            output: CommandOutput::Discard,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unlatching_turns_the_light_off() {
+        let (mut dispatcher, mut events) = dispatcher();
+        let pad = (0, Trigger::Note(MidiNote::C(5)));
+        let action = Action::Key(Key::KeyW.into());
+
+        dispatcher.toggle_latch(pad, &action).await;
+        assert!(dispatcher.latched.contains_key(&pad));
+        assert_eq!(drain(&mut events), vec![]);
+
+        dispatcher.toggle_latch(pad, &action).await;
+        assert!(dispatcher.latched.is_empty());
+        assert_eq!(drain(&mut events), vec![OverlayEvent::Light(pad.1, Light::Idle)]);
+    }
+
+    #[tokio::test]
+    async fn test_exit_light_does_not_reset_a_newer_run() {
+        let (mut dispatcher, mut events) = dispatcher();
+        let pad = (0, Trigger::Note(MidiNote::C(5)));
+
+        let first = dispatcher.command_light(pad);
+        first(CommandStatus::Running);
+        first(CommandStatus::Exited(true));
+        let second = dispatcher.command_light(pad);
+        second(CommandStatus::Running);
+        // the first run is outdated now
+        first(CommandStatus::Exited(false));
+        tokio::time::sleep(EXIT_LIGHT_TIME + Duration::from_millis(100)).await;
+
+        assert_eq!(
+            drain(&mut events),
+            vec![
+                OverlayEvent::Light(pad.1, Light::Running),
+                OverlayEvent::Light(pad.1, Light::Succeeded),
+                OverlayEvent::Light(pad.1, Light::Running),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_exit_light_after_release() {
+        let (mut dispatcher, mut events) = dispatcher();
+        let pad = (0, Trigger::Note(MidiNote::C(5)));
+        let action = shell("sleep 0.2", true);
+
+        dispatcher.activate(pad, &action).await;
+        dispatcher.deactivate(pad, &action).await;
+        // ignored, the first run is still going
+        dispatcher.activate(pad, &action).await;
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(
+            drain(&mut events),
+            vec![
+                OverlayEvent::Light(pad.1, Light::Running),
+                OverlayEvent::Light(pad.1, Light::Succeeded),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_commands_survive_a_pause() {
+        let (mut dispatcher, _events) = dispatcher();
+        let pad = (0, Trigger::Note(MidiNote::C(5)));
+        let action = shell("sleep 10", false);
+
+        dispatcher.activate(pad, &action).await;
+        dispatcher.toggle_pause().await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(dispatcher.commands[&pad].iter().all(|c| !c.is_finished()));
+
+        dispatcher.shutdown().await;
+        assert!(dispatcher.commands.is_empty());
+    }
+}
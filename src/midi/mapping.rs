@@ -0,0 +1,451 @@
+use log::{debug, warn};
+use once_cell::sync::Lazy;
+use serde::{
+    Deserialize, Serialize,
+    de::{self, Visitor},
+};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    sync::Mutex,
+};
+
+use crate::{
+    config::{Binding, Combo, LayerMode, LayerSwitch},
+    midi::{message::MidiChannel, note::MidiNote},
+};
+
+pub static MAPPING: Lazy<Mutex<Keymap>> = Lazy::new(|| Mutex::new(Keymap::default()));
+
+/// Bindings of one layer
+pub type Mapping = HashMap<MappingKey, Binding>;
+
+pub fn set_mapping(
+    base: Mapping,
+    layers: HashMap<String, Mapping>,
+    chords: HashMap<PadChord, Binding>,
+    combos: Vec<Combo>,
+) {
+    for (t, ac) in base.iter() {
+        debug!("MAPPING: {:?} = {:?}", ac, t);
+    }
+    for (name, layer) in layers.iter() {
+        for (t, ac) in layer.iter() {
+            debug!("MAPPING [{}]: {:?} = {:?}", name, ac, t);
+        }
+    }
+
+    for (chord, ac) in chords.iter() {
+        debug!("MAPPING: {:?} = {:?}", ac, chord);
+    }
+    for combo in combos.iter() {
+        debug!("MAPPING: {:?} = {:?}", combo.action, combo.steps);
+    }
+
+    let mut mapping = MAPPING.lock().unwrap();
+    mapping.base.extend(base);
+    mapping.layers.extend(layers);
+    mapping.chords.extend(chords);
+    mapping.combos.extend(combos);
+}
+
+/// Looks up the binding of a trigger on the active layers
+pub fn lookup(channel: MidiChannel, trigger: Trigger) -> Option<Binding> {
+    MAPPING.lock().unwrap().lookup(channel, trigger)
+}
+
+/// The base mapping and the named layers stacked on top of it
+#[derive(Default, Debug)]
+pub struct Keymap {
+    base: Mapping,
+    layers: HashMap<String, Mapping>,
+
+    /// Active layers, the last one is on top
+    active: Vec<String>,
+
+    /// Layer on top of everything for the next pad press only
+    oneshot: Option<String>,
+
+    /// Bindings of several pads pressed together, independent of layers
+    chords: HashMap<PadChord, Binding>,
+
+    /// Pads pressed in order, independent of layers
+    combos: Vec<Combo>,
+}
+
+impl Keymap {
+    /// Active mappings from the top layer down to the base
+    fn stack(&self) -> impl Iterator<Item = &Mapping> {
+        self.oneshot
+            .iter()
+            .chain(self.active.iter().rev())
+            .filter_map(|name| self.layers.get(name))
+            .chain(std::iter::once(&self.base))
+    }
+
+    /// Finds the topmost binding of a trigger, unmapped triggers fall through to the layer below.
+    /// Inside a layer, entries bound to the channel are preferred.
+    pub fn lookup(&self, channel: MidiChannel, trigger: Trigger) -> Option<Binding> {
+        self.stack()
+            .find_map(|m| {
+                m.get(&MappingKey { channel: Some(channel), trigger })
+                    .or_else(|| m.get(&MappingKey { channel: None, trigger }))
+            })
+            .cloned()
+    }
+
+    /// All bindings that are currently reachable
+    pub fn visible(&self) -> Mapping {
+        let mut visible = Mapping::new();
+        for m in self.stack() {
+            for (key, binding) in m {
+                visible.entry(*key).or_insert_with(|| binding.clone());
+            }
+        }
+        visible
+    }
+
+    /// Bindings of all layers, active or not
+    pub fn all(&self) -> Mapping {
+        let mut all = Mapping::new();
+        for m in self.layers.values().chain(std::iter::once(&self.base)) {
+            all.extend(m.iter().map(|(k, b)| (*k, b.clone())));
+        }
+        all
+    }
+
+    /// Applies a layer switch on press or release, returns whether the active layers changed
+    pub fn switch(&mut self, switch: &LayerSwitch, pressed: bool) -> bool {
+        if !self.layers.contains_key(&switch.name) {
+            warn!("unknown layer: {}", switch.name);
+            return false;
+        }
+
+        let position = self.active.iter().position(|name| *name == switch.name);
+        match (switch.mode, pressed, position) {
+            (LayerMode::Hold, true, None) | (LayerMode::Toggle, true, None) => {
+                self.active.push(switch.name.clone());
+                true
+            }
+            (LayerMode::Hold, false, Some(i)) | (LayerMode::Toggle, true, Some(i)) => {
+                self.active.remove(i);
+                true
+            }
+            (LayerMode::OneShot, true, _) => {
+                self.oneshot.replace(switch.name.clone()).as_ref() != Some(&switch.name)
+            }
+            _ => false,
+        }
+    }
+
+    pub fn chords(&self) -> Vec<(PadChord, Binding)> {
+        self.chords.iter().map(|(c, b)| (c.clone(), b.clone())).collect()
+    }
+
+    pub fn combos(&self) -> Vec<Combo> {
+        self.combos.clone()
+    }
+
+    /// Drops the one-shot layer after it was used for a press, returns whether it was active
+    pub fn consume_oneshot(&mut self) -> bool {
+        self.oneshot.take().is_some()
+    }
+}
+
+/// A trigger as it was received from the device
+pub type Control = (MidiChannel, Trigger);
+
+/// Key of the `[mapping]` table: a trigger, optionally restricted to one channel (`ch2:C4`)
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq, Serialize)]
+pub struct MappingKey {
+    /// Zero based channel, `None` matches all channels
+    pub channel: Option<MidiChannel>,
+    pub trigger: Trigger,
+}
+
+impl MappingKey {
+    /// Whether a control received from the device is meant by this key
+    pub fn matches(&self, control: Control) -> bool {
+        self.channel.is_none_or(|ch| ch == control.0) && self.trigger == control.1
+    }
+
+    pub(crate) fn parse(value: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let value = value.trim();
+
+        match value.split_once(':') {
+            Some((prefix, trigger)) if prefix.starts_with("ch") => {
+                let channel: u8 = prefix[2..].parse()?;
+                if !(1..=16).contains(&channel) {
+                    return Err(Box::new(TriggerError::ChannelOutOfRange(channel)));
+                }
+                Ok(MappingKey {
+                    channel: Some(channel - 1),
+                    trigger: Trigger::parse(trigger)?,
+                })
+            }
+            _ => Ok(MappingKey {
+                channel: None,
+                trigger: Trigger::parse(value)?,
+            }),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MappingKey {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct MappingKeyVisitor;
+
+        impl<'de> Visitor<'de> for MappingKeyVisitor {
+            type Value = MappingKey;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a trigger like C5, cc:91 or pitchbend, optionally prefixed with a channel like ch2:")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                MappingKey::parse(v).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(MappingKeyVisitor)
+    }
+}
+
+/// Key of the `[chords]` table: pads that have to be pressed together (`C3+E3+G3`)
+#[derive(Debug, Clone, Eq, Hash, PartialEq, Serialize)]
+pub struct PadChord(pub Vec<MappingKey>);
+
+impl PadChord {
+    pub(crate) fn parse(value: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let pads = value.split('+').map(MappingKey::parse).collect::<Result<Vec<_>, _>>()?;
+        if pads.len() < 2 {
+            return Err(format!("a chord needs at least two pads: {}", value).into());
+        }
+        Ok(PadChord(pads))
+    }
+
+    pub fn contains(&self, control: Control) -> bool {
+        self.0.iter().any(|key| key.matches(control))
+    }
+
+    /// Whether every pad of the chord is among the controls
+    pub fn is_complete(&self, controls: &[Control]) -> bool {
+        self.0.iter().all(|key| controls.iter().any(|c| key.matches(*c)))
+    }
+}
+
+impl<'de> Deserialize<'de> for PadChord {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct PadChordVisitor;
+
+        impl<'de> Visitor<'de> for PadChordVisitor {
+            type Value = PadChord;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "triggers joined with +, like C3+E3+G3")
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                PadChord::parse(v).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_str(PadChordVisitor)
+    }
+}
+
+/// Something on the device that can be bound to an [Action](crate::config::Action)
+#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq, Serialize)]
+pub enum Trigger {
+    /// A pad or key sending NoteOn/NoteOff
+    Note(MidiNote),
+
+    /// A button or fader sending Control Change messages (`cc:91`)
+    ControlChange(u8),
+
+    /// The pitch bend wheel or strip (`pitchbend`)
+    PitchBend,
+}
+
+impl Trigger {
+    pub(crate) fn parse(value: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let value = value.trim();
+
+        match value.split_once(':') {
+            Some(("cc", controller)) => {
+                let controller: u8 = controller.trim().parse()?;
+                if controller > 127 {
+                    return Err(Box::new(TriggerError::ControllerOutOfRange(controller)));
+                }
+                Ok(Trigger::ControlChange(controller))
+            }
+            Some((prefix, _)) => Err(Box::new(TriggerError::UnknownPrefix(prefix.to_string()))),
+            None if value == "pitchbend" => Ok(Trigger::PitchBend),
+            None => Ok(Trigger::Note(MidiNote::parse(value)?)),
+        }
+    }
+}
+
+impl Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trigger::Note(note) => write!(f, "{}", note),
+            Trigger::ControlChange(cc) => write!(f, "cc:{}", cc),
+            Trigger::PitchBend => write!(f, "pitchbend"),
+        }
+    }
+}
+
+impl From<MidiNote> for Trigger {
+    fn from(value: MidiNote) -> Self {
+        Trigger::Note(value)
+    }
+}
+
+// MARK: Errors
+#[derive(Debug)]
+enum TriggerError {
+    UnknownPrefix(String),
+    ControllerOutOfRange(u8),
+    ChannelOutOfRange(u8),
+}
+
+impl std::error::Error for TriggerError {}
+
+impl Display for TriggerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TriggerError::UnknownPrefix(prefix) => write!(f, "unknown trigger prefix: {}", prefix),
+            TriggerError::ControllerOutOfRange(cc) => {
+                write!(f, "controller number out of range: {}", cc)
+            }
+            TriggerError::ChannelOutOfRange(ch) => write!(f, "channel out of range: {}", ch),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Keymap, Mapping, MappingKey, Trigger};
+    use crate::{
+        config::{Action, Binding, LayerMode, LayerSwitch},
+        midi::note::MidiNote,
+    };
+
+    fn key(note: MidiNote) -> MappingKey {
+        MappingKey { channel: None, trigger: note.into() }
+    }
+
+    fn binding(key: rdev::Key) -> Binding {
+        Binding {
+            action: Action::Key(key.into()),
+            pressure: None,
+            turbo: None,
+            latch: false,
+            min_velocity: None,
+            zones: Vec::new(),
+            shift: None,
+        }
+    }
+
+    fn keymap() -> Keymap {
+        let base = Mapping::from([
+            (key(MidiNote::C(4)), binding(rdev::Key::KeyA)),
+            (key(MidiNote::D(4)), binding(rdev::Key::KeyB)),
+        ]);
+        let combat = Mapping::from([(key(MidiNote::C(4)), binding(rdev::Key::KeyC))]);
+
+        Keymap {
+            base,
+            layers: [("combat".to_string(), combat)].into(),
+            ..Default::default()
+        }
+    }
+
+    fn pressed(keymap: &Keymap, note: MidiNote) -> Option<rdev::Key> {
+        match keymap.lookup(0, note.into()).map(|b| b.action) {
+            Some(Action::Key(chord)) => chord.0.first().copied(),
+            _ => None,
+        }
+    }
+
+    fn switch(mode: LayerMode) -> LayerSwitch {
+        LayerSwitch { name: "combat".to_string(), mode }
+    }
+
+    #[test]
+    fn test_layers_fall_through() {
+        let mut keymap = keymap();
+        assert_eq!(pressed(&keymap, MidiNote::C(4)), Some(rdev::Key::KeyA));
+
+        assert!(keymap.switch(&switch(LayerMode::Hold), true));
+        assert_eq!(pressed(&keymap, MidiNote::C(4)), Some(rdev::Key::KeyC));
+        assert_eq!(pressed(&keymap, MidiNote::D(4)), Some(rdev::Key::KeyB));
+        assert_eq!(keymap.visible().len(), 2);
+
+        assert!(keymap.switch(&switch(LayerMode::Hold), false));
+        assert_eq!(pressed(&keymap, MidiNote::C(4)), Some(rdev::Key::KeyA));
+    }
+
+    #[test]
+    fn test_toggle_and_oneshot() {
+        let mut keymap = keymap();
+
+        assert!(keymap.switch(&switch(LayerMode::Toggle), true));
+        assert!(!keymap.switch(&switch(LayerMode::Toggle), false));
+        assert_eq!(pressed(&keymap, MidiNote::C(4)), Some(rdev::Key::KeyC));
+        assert!(keymap.switch(&switch(LayerMode::Toggle), true));
+        assert_eq!(pressed(&keymap, MidiNote::C(4)), Some(rdev::Key::KeyA));
+
+        assert!(keymap.switch(&switch(LayerMode::OneShot), true));
+        assert_eq!(pressed(&keymap, MidiNote::C(4)), Some(rdev::Key::KeyC));
+        assert!(keymap.consume_oneshot());
+        assert!(!keymap.consume_oneshot());
+        assert_eq!(pressed(&keymap, MidiNote::C(4)), Some(rdev::Key::KeyA));
+
+        let unknown = LayerSwitch { name: "nope".to_string(), mode: LayerMode::Hold };
+        assert!(!keymap.switch(&unknown, true));
+    }
+
+    #[test]
+    fn test_parsing() {
+        assert_eq!(Trigger::parse("cc:91").unwrap(), Trigger::ControlChange(91));
+        assert_eq!(Trigger::parse(" cc: 19 ").unwrap(), Trigger::ControlChange(19));
+        assert_eq!(Trigger::parse("C5").unwrap(), Trigger::Note(MidiNote::C(5)));
+        assert_eq!(Trigger::parse("pitchbend").unwrap(), Trigger::PitchBend);
+    }
+
+    #[test]
+    fn test_invalid_controllers_fail() {
+        assert!(Trigger::parse("cc:128").is_err());
+        assert!(Trigger::parse("cc:abc").is_err());
+        assert!(Trigger::parse("pc:1").is_err());
+    }
+
+    #[test]
+    fn test_channel_prefix() {
+        let key = MappingKey::parse("ch2:C4").unwrap();
+        assert_eq!(key.channel, Some(1));
+        assert_eq!(key.trigger, Trigger::Note(MidiNote::C(4)));
+
+        let key = MappingKey::parse("ch16:cc:91").unwrap();
+        assert_eq!(key.channel, Some(15));
+        assert_eq!(key.trigger, Trigger::ControlChange(91));
+
+        assert_eq!(MappingKey::parse("C4").unwrap().channel, None);
+        assert!(MappingKey::parse("ch0:C4").is_err());
+        assert!(MappingKey::parse("ch17:C4").is_err());
+    }
+}
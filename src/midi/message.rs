@@ -4,8 +4,8 @@ use log::error;
 use crate::midi::note::MidiNote;
 
 //The whole message that was sent from the MIDI Device
-#[derive(Debug, Clone, Copy)]
-pub struct Message(u64, pub MidiMessage);
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Message(pub u64, pub MidiMessage);
 
 impl From<(u64, Vec<u8>)> for Message {
     fn from(value: (u64, Vec<u8>)) -> Self {
@@ -15,22 +15,43 @@ impl From<(u64, Vec<u8>)> for Message {
 
 pub type MidiChannel = u8;
 pub type MidiVelocity = u8;
+pub type MidiController = u8;
+pub type MidiValue = u8;
+pub type MidiPitch = u16;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MidiMessage {
     NoteOn(MidiChannel, MidiNote, MidiVelocity),
     NoteOff(MidiChannel, MidiNote),
     AfterTouch(MidiChannel, MidiNote, MidiVelocity),
+    ControlChange(MidiChannel, MidiController, MidiValue),
+    ChannelPressure(MidiChannel, MidiValue),
+    PitchBend(MidiChannel, MidiPitch),
     Clock,
     Unknown,
 }
 
+impl MidiMessage {
+    /// How hard a pad or button was hit, full velocity for messages without one
+    pub fn velocity(&self) -> MidiVelocity {
+        match self {
+            MidiMessage::NoteOn(_, _, vel) | MidiMessage::ControlChange(_, _, vel) => *vel,
+            _ => 127,
+        }
+    }
+}
+
 impl From<MidiMessage> for Vec<u8> {
     fn from(value: MidiMessage) -> Self {
         match value {
             MidiMessage::NoteOn(ch, note, vel) => vec![0x90 + ch, note.into(), vel],
             MidiMessage::NoteOff(ch, note) => vec![0x80 + ch, note.into(), 0],
             MidiMessage::AfterTouch(ch, note, vel) => vec![0xA0 + ch, note.into(), vel],
+            MidiMessage::ControlChange(ch, cc, val) => vec![0xB0 + ch, cc, val],
+            MidiMessage::ChannelPressure(ch, val) => vec![0xD0 + ch, val],
+            MidiMessage::PitchBend(ch, pitch) => {
+                vec![0xE0 + ch, (pitch & 0x7F) as u8, (pitch >> 7) as u8]
+            }
             MidiMessage::Unknown => todo!(),
             MidiMessage::Clock => todo!(),
         }
@@ -62,6 +83,18 @@ impl From<Vec<u8>> for MidiMessage {
                 let n = (*note).into();
                 Self::AfterTouch(ch, n, *vel)
             },
+            [0xB0..=0xBF, cc, val] => {
+                let ch = data[0] - 0xB0;
+                Self::ControlChange(ch, *cc, *val)
+            },
+            [0xD0..=0xDF, val] => {
+                let ch = data[0] - 0xD0;
+                Self::ChannelPressure(ch, *val)
+            },
+            [0xE0..=0xEF, lsb, msb] => {
+                let ch = data[0] - 0xE0;
+                Self::PitchBend(ch, ((*msb as u16) << 7) | *lsb as u16)
+            },
             [0xF8] => {
                 Self::Clock
             }
@@ -71,4 +104,27 @@ impl From<Vec<u8>> for MidiMessage {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MidiMessage;
+
+    #[test]
+    fn test_control_change_round_trip() {
+        let parsed: MidiMessage = vec![0xB1, 91, 127].into();
+        assert!(matches!(parsed, MidiMessage::ControlChange(1, 91, 127)));
+
+        let data: Vec<u8> = parsed.into();
+        assert_eq!(data, vec![0xB1, 91, 127]);
+    }
+
+    #[test]
+    fn test_pitch_bend_round_trip() {
+        let parsed: MidiMessage = vec![0xE0, 0x00, 0x40].into();
+        assert!(matches!(parsed, MidiMessage::PitchBend(0, 8192)));
+
+        let data: Vec<u8> = MidiMessage::PitchBend(2, 16383).into();
+        assert_eq!(data, vec![0xE2, 0x7F, 0x7F]);
+    }
+}
@@ -3,8 +3,28 @@ pub(crate) mod message;
 
 pub(crate) mod note;
 
+/// Binding of device inputs to actions
+pub(crate) mod mapping;
+
 pub(crate) mod device;
 
 pub(crate) mod output;
 
-pub(crate) mod input;
\ No newline at end of file
+pub(crate) mod input;
+
+/// Pressure thresholds of held pads
+pub(crate) mod pressure;
+
+/// Tap, hold and double tap detection
+pub(crate) mod gesture;
+/// Resolution of opposite directions held together
+pub(crate) mod socd;
+
+/// Mutually exclusive pads
+pub(crate) mod radio;
+
+/// Several pads pressed together
+pub(crate) mod chord;
+
+/// Pads pressed in order
+pub(crate) mod combo;
@@ -1,30 +1,8 @@
-use log::debug;
-use once_cell::sync::Lazy;
 use serde::{
     Deserialize, Serialize,
     de::{self, Visitor},
 };
-use std::{
-    collections::HashMap,
-    fmt::{self, Display},
-    sync::Mutex,
-};
-
-use crate::config::Action;
-
-pub static MAPPING: Lazy<Mutex<HashMap<MidiNote, Action>>> =
-    Lazy::new(|| Mutex::new(HashMap::new()));
-
-pub fn set_mapping(m: HashMap<MidiNote, Action>) {
-    for (_, (m, ac)) in m.iter().enumerate() {
-        debug!("MAPPING: {:?} = {:?}", ac, m);
-
-        {
-            let mut mapping = MAPPING.lock().unwrap();
-            mapping.insert(*m, ac.clone());
-        }
-    }
-}
+use std::fmt::{self, Display};
 
 #[derive(Debug, Clone, Copy, Eq, Hash, PartialEq, Serialize)]
 pub enum MidiNote {
@@ -69,7 +47,7 @@ impl<'de> Deserialize<'de> for MidiNote {
 }
 
 impl MidiNote {
-    fn parse(value: &str) -> Result<Self, MappingError> {
+    pub(crate) fn parse(value: &str) -> Result<Self, MappingError> {
         let value = value.trim();
         let (note_str, octave_str) = value
             .chars()
@@ -78,11 +56,11 @@ impl MidiNote {
         // Handle Octave, inklusive negatives
         let octave: u8 = octave_str
             .parse()
-            .unwrap_or_else(|_| panic!("Invalid octave in note string: {}", value));
+            .map_err(|_| MappingError::InvalidOctave)?;
 
         //let octave = octave  2;
 
-        match note_str.as_str() {
+        let note = match note_str.as_str() {
             "C" => Ok(MidiNote::C(octave)),
             "C#" | "CS" => Ok(MidiNote::CS(octave)),
             "D" => Ok(MidiNote::D(octave)),
@@ -96,7 +74,35 @@ impl MidiNote {
             "A#" | "AS" => Ok(MidiNote::AS(octave)),
             "B" => Ok(MidiNote::B(octave)),
             _ => Err(MappingError::UnknownMidiKey), //_ => panic!("Unknown note string: {}", value),
+        }?;
+
+        // the highest note MIDI can address is G10
+        if octave > 10 || u8::from(note) > 127 {
+            return Err(MappingError::NoteOutOfRange);
         }
+
+        Ok(note)
+    }
+}
+
+/// Written the way the mapping names notes, sharps with `S` (`FS3`)
+impl Display for MidiNote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, octave) = match self {
+            MidiNote::C(oct) => ("C", oct),
+            MidiNote::CS(oct) => ("CS", oct),
+            MidiNote::D(oct) => ("D", oct),
+            MidiNote::DS(oct) => ("DS", oct),
+            MidiNote::E(oct) => ("E", oct),
+            MidiNote::F(oct) => ("F", oct),
+            MidiNote::FS(oct) => ("FS", oct),
+            MidiNote::G(oct) => ("G", oct),
+            MidiNote::GS(oct) => ("GS", oct),
+            MidiNote::A(oct) => ("A", oct),
+            MidiNote::AS(oct) => ("AS", oct),
+            MidiNote::B(oct) => ("B", oct),
+        };
+        write!(f, "{}{}", name, octave)
     }
 }
 
@@ -144,18 +150,12 @@ impl From<MidiNote> for u8 {
     }
 }
 
-impl From<MidiNote> for Option<Action> {
-    fn from(value: MidiNote) -> Self {
-        let m = MAPPING.lock().unwrap();
-
-        m.get(&value.into()).cloned()
-    }
-}
-
 // MARK: Errors
 #[derive(Debug)]
-enum MappingError {
+pub(crate) enum MappingError {
     UnknownMidiKey,
+    InvalidOctave,
+    NoteOutOfRange,
 }
 
 impl std::error::Error for MappingError {}
@@ -168,13 +168,24 @@ impl Display for MappingError {
 
 #[cfg(test)]
 mod tests {
+    use super::MidiNote;
+
     #[test]
     fn test_parsing() {
-        assert_eq!(true, false); // TODO Implement
+        assert_eq!(MidiNote::parse("C5").unwrap(), MidiNote::C(5));
+        assert_eq!(MidiNote::parse("F#3").unwrap(), MidiNote::FS(3));
+        assert_eq!(MidiNote::parse("FS3").unwrap(), MidiNote::FS(3));
+        assert_eq!(MidiNote::parse(" G10 ").unwrap(), MidiNote::G(10));
+        assert_eq!(u8::from(MidiNote::parse("A3").unwrap()), 45);
+        assert_eq!(MidiNote::parse("F#3").unwrap().to_string(), "FS3");
     }
 
     #[test]
     fn test_unsupported_notes_fail() {
-        assert_eq!(true, false); // TODO Implement
+        assert!(MidiNote::parse("H3").is_err());
+        assert!(MidiNote::parse("C").is_err());
+        assert!(MidiNote::parse("C-1").is_err());
+        assert!(MidiNote::parse("G#10").is_err());
+        assert!(MidiNote::parse("C11").is_err());
     }
 }
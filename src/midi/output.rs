@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use log::{debug, info, trace};
 use midir::{MidiOutputConnection, SendError};
@@ -6,22 +6,87 @@ use tokio::sync::broadcast;
 
 use crate::{
     DeviceNameRetrieve,
-    config::Config,
-    midi::{message::MidiMessage, note::MAPPING},
+    midi::{
+        mapping::{MAPPING, Trigger},
+        message::MidiMessage,
+    },
 };
 
 type OutputTaskReturn = Result<(), SendError>;
 
 const COLOR_PAD_ON: u8 = 120;
 const COLOR_PAD_OFF: u8 = 11;
+const COLOR_PAD_LATCHED: u8 = 45;
+const COLOR_PAD_SELECTED: u8 = 53;
+const COLOR_PAD_RUNNING: u8 = 41;
+const COLOR_PAD_SUCCEEDED: u8 = 21;
+const COLOR_PAD_FAILED: u8 = 5;
+const COLOR_PAD_SHIFTED: u8 = 9;
+const COLOR_PAD_PAUSED: u8 = 1;
 
 pub trait OutputDeviceNameRetrieve: DeviceNameRetrieve {
     fn get_light_status(&self) -> bool;
 }
 
+/// Feedback from the input task to the overlay
+#[derive(Debug, Clone, PartialEq)]
+pub enum OverlayEvent {
+    /// A mapped pad was pressed or released
+    Pad(MidiMessage),
+
+    /// The active layers changed, the mapping has to be drawn again
+    Redraw,
+
+    /// Sets the light of a mapped pad independently of presses
+    Light(Trigger, Light),
+
+    /// A shift modifier was pressed or released, pads with a `shift` action change their color
+    Shift(bool),
+
+    /// Dispatching was suspended or resumed, the mapping is dimmed while paused
+    Paused(bool),
+}
+
+/// States a mapped pad can be shown in
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Light {
+    Idle,
+    Active,
+
+    /// The action stays active after the pad was released
+    Latched,
+
+    /// The pad is the selected one of its radio group
+    Selected,
+
+    /// A command started by the pad is running
+    Running,
+
+    /// A command started by the pad just exited successfully
+    Succeeded,
+
+    /// A command started by the pad just failed
+    Failed,
+}
+
+impl Light {
+    fn color(self, trigger: Trigger, shifted: bool) -> u8 {
+        match self {
+            Light::Idle if shifted && has_shift(trigger) => COLOR_PAD_SHIFTED,
+            Light::Idle => COLOR_PAD_OFF,
+            Light::Active => COLOR_PAD_ON,
+            Light::Latched => COLOR_PAD_LATCHED,
+            Light::Selected => COLOR_PAD_SELECTED,
+            Light::Running => COLOR_PAD_RUNNING,
+            Light::Succeeded => COLOR_PAD_SUCCEEDED,
+            Light::Failed => COLOR_PAD_FAILED,
+        }
+    }
+}
+
 pub async fn start_overlay_task<C>(
     config: C,
-    mut receiver: broadcast::Receiver<MidiMessage>,
+    mut receiver: broadcast::Receiver<OverlayEvent>,
     output_port: MidiOutputConnection,
     mut cancellation: broadcast::Receiver<()>,
 ) -> OutputTaskReturn
@@ -30,14 +95,58 @@ where
 {
     let output_port = Arc::new(std::sync::Mutex::new(output_port));
     if config.get_light_status() {
-        draw_mapping(&output_port).await?;
+        draw_mapping(false, &output_port).await?;
+
+        // pads that keep a state after their release, drawn again after a redraw
+        let mut persistent: HashMap<Trigger, Light> = HashMap::new();
+        let mut shifted = false;
+        let mut paused = false;
 
         let _last_len = 0;
         loop {
             tokio::select! {
-                Ok(msg) = receiver.recv() => {
-
-                    draw_active(msg, &output_port).await?;
+                Ok(event) = receiver.recv() => {
+                    match event {
+                        OverlayEvent::Paused(on) => {
+                            paused = on;
+                            send_all_off(&output_port).await?;
+                            if paused {
+                                draw_paused(&output_port).await?;
+                            } else {
+                                draw_mapping(shifted, &output_port).await?;
+                                for (trigger, light) in persistent.iter() {
+                                    draw_light(*trigger, *light, shifted, &output_port).await?;
+                                }
+                            }
+                        }
+                        // commands keep running while paused, their state is drawn when resuming
+                        OverlayEvent::Light(trigger, light) => {
+                            match light {
+                                Light::Latched | Light::Selected | Light::Running => persistent.insert(trigger, light),
+                                _ => persistent.remove(&trigger),
+                            };
+                            if !paused {
+                                draw_light(trigger, light, shifted, &output_port).await?
+                            }
+                        }
+                        // nothing is dispatched while paused, keep the dimmed mapping
+                        _ if paused => {}
+                        OverlayEvent::Pad(msg) => draw_active(msg, &persistent, shifted, &output_port).await?,
+                        OverlayEvent::Redraw => {
+                            send_all_off(&output_port).await?;
+                            draw_mapping(shifted, &output_port).await?;
+                            for (trigger, light) in persistent.iter() {
+                                draw_light(*trigger, *light, shifted, &output_port).await?;
+                            }
+                        }
+                        OverlayEvent::Shift(on) => {
+                            shifted = on;
+                            draw_mapping(shifted, &output_port).await?;
+                            for (trigger, light) in persistent.iter() {
+                                draw_light(*trigger, *light, shifted, &output_port).await?;
+                            }
+                        }
+                    }
                 }
                 _c = cancellation.recv() => {
                     debug!("closing output task");
@@ -55,48 +164,109 @@ where
     }
 }
 
-/// Draws the velocities on the hardware
+/// Draws the velocities on the hardware, released pads go back to their persistent state
 async fn draw_active(
     message: MidiMessage,
+    persistent: &HashMap<Trigger, Light>,
+    shifted: bool,
     output: &Arc<std::sync::Mutex<MidiOutputConnection>>,
 ) -> Result<(), SendError> {
-    match message {
-        MidiMessage::NoteOn(ch, note, vel) => {
-            let new_msg: Vec<u8> = MidiMessage::NoteOn(0, note, COLOR_PAD_ON).into();
-            let mut lock = output.lock().expect("error acquiring output lock");
-            lock.send(&new_msg).unwrap();
-            trace!("{:?}", new_msg);
-        }
+    let (trigger, light) = match message {
+        MidiMessage::NoteOn(_ch, note, _vel) => (Trigger::Note(note), Light::Active),
+        MidiMessage::NoteOff(_ch, note) => (Trigger::Note(note), Light::Idle),
+        MidiMessage::ControlChange(_ch, cc, 0) => (Trigger::ControlChange(cc), Light::Idle),
+        MidiMessage::ControlChange(_ch, cc, _val) => (Trigger::ControlChange(cc), Light::Active),
+        _ => return Ok(()),
+    };
+    let light = match light {
+        Light::Idle => persistent.get(&trigger).copied().unwrap_or(light),
+        light => light,
+    };
 
-        MidiMessage::NoteOff(ch, note) => {
-            let new_msg: Vec<u8> = MidiMessage::NoteOn(0, note, COLOR_PAD_OFF).into();
-            let mut lock = output.lock().expect("error acquiring output lock");
-            lock.send(&new_msg).unwrap();
-            trace!("{:?}", new_msg);
-        }
-        _ => {}
+    draw_light(trigger, light, shifted, output).await
+}
+
+/// Shows a pad in the given state
+async fn draw_light(
+    trigger: Trigger,
+    light: Light,
+    shifted: bool,
+    output: &Arc<std::sync::Mutex<MidiOutputConnection>>,
+) -> Result<(), SendError> {
+    if let Some(new_msg) = light_message(trigger, light.color(trigger, shifted)) {
+        let new_msg: Vec<u8> = new_msg.into();
+        let mut lock = output.lock().expect("error acquiring output lock");
+        lock.send(&new_msg).unwrap();
+        trace!("{:?}", new_msg);
     }
 
     Ok(())
 }
 
-/// Initially draw the mapping on the device
+/// Builds the message that sets the LED of the given trigger to a palette color
+fn light_message(trigger: Trigger, color: u8) -> Option<MidiMessage> {
+    match trigger {
+        Trigger::Note(note) => Some(MidiMessage::NoteOn(0, note, color)),
+        Trigger::ControlChange(cc) => Some(MidiMessage::ControlChange(0, cc, color)),
+        Trigger::PitchBend => None,
+    }
+}
+
+/// Whether the visible binding of the trigger has a `shift` action
+fn has_shift(trigger: Trigger) -> bool {
+    MAPPING
+        .lock()
+        .unwrap()
+        .visible()
+        .iter()
+        .any(|(key, binding)| key.trigger == trigger && binding.shift.is_some())
+}
+
+/// Initially draw the mapping on the device, pads with a `shift` action are highlighted while shifted
 async fn draw_mapping(
+    shifted: bool,
     output: &Arc<std::sync::Mutex<MidiOutputConnection>>,
 ) -> Result<(), SendError> {
-    let mapping = MAPPING.lock().unwrap();
+    let mapping = MAPPING.lock().unwrap().visible();
 
     let mut lock = output.lock().expect("error acquiring output lock");
 
     debug!("Sending Overlay: {:?}", mapping.keys());
-    for m in mapping.keys() {
-        let msg: Vec<u8> = MidiMessage::NoteOn(0, *m, COLOR_PAD_OFF).into();
-        lock.send(&msg)?;
+    for (m, binding) in mapping.iter() {
+        // faders light themselves
+        if binding.action.is_continuous() {
+            continue;
+        }
+
+        let color = if shifted && binding.shift.is_some() { COLOR_PAD_SHIFTED } else { COLOR_PAD_OFF };
+        if let Some(msg) = light_message(m.trigger, color) {
+            let msg: Vec<u8> = msg.into();
+            lock.send(&msg)?;
+        }
     }
     drop(lock);
     Ok(())
 }
 
+/// Draws the mapping dimmed
+async fn draw_paused(
+    output: &Arc<std::sync::Mutex<MidiOutputConnection>>,
+) -> Result<(), SendError> {
+    let mapping = MAPPING.lock().unwrap().visible();
+
+    let mut lock = output.lock().expect("error acquiring output lock");
+    for (m, binding) in mapping.iter() {
+        if binding.action.is_continuous() {
+            continue;
+        }
+        if let Some(msg) = light_message(m.trigger, COLOR_PAD_PAUSED) {
+            let msg: Vec<u8> = msg.into();
+            lock.send(&msg)?;
+        }
+    }
+    Ok(())
+}
+
 /// Send MidiOff to all notes
 async fn send_all_off(
     output: &Arc<std::sync::Mutex<MidiOutputConnection>>,
@@ -107,5 +277,14 @@ async fn send_all_off(
         let note: Vec<u8> = MidiMessage::NoteOff(0, index.into()).into();
         lock.send(&note)?;
     }
+
+    // buttons sending CC are lit through CC as well
+    let mapping = MAPPING.lock().unwrap().all();
+    for (m, binding) in mapping.iter() {
+        if let (Trigger::ControlChange(cc), false) = (m.trigger, binding.action.is_continuous()) {
+            let msg: Vec<u8> = MidiMessage::ControlChange(0, cc, 0).into();
+            lock.send(&msg)?;
+        }
+    }
     Ok(())
 }
@@ -0,0 +1,116 @@
+use std::collections::HashSet;
+
+use crate::{
+    config::PressureBinding,
+    midi::{message::MidiChannel, note::MidiNote},
+};
+
+/// A pad as it was received from the device
+pub type Pad = (MidiChannel, MidiNote);
+
+/// What has to happen with the pressure action of a pad
+#[derive(Debug, PartialEq)]
+pub enum PressureChange {
+    Engage,
+    Release,
+}
+
+/// Tracks held pads and which of them have their pressure action engaged
+#[derive(Default)]
+pub struct PressureTracker {
+    held: HashSet<Pad>,
+    engaged: HashSet<Pad>,
+}
+
+impl PressureTracker {
+    pub fn press(&mut self, pad: Pad) {
+        self.held.insert(pad);
+    }
+
+    /// Forgets the pad, returns whether its pressure action was still engaged
+    pub fn release(&mut self, pad: Pad) -> bool {
+        self.held.remove(&pad);
+        self.engaged.remove(&pad)
+    }
+
+    /// Pads held on the given channel, channel pressure applies to all of them
+    pub fn held_on(&self, channel: MidiChannel) -> Vec<MidiNote> {
+        self.held
+            .iter()
+            .filter(|(ch, _)| *ch == channel)
+            .map(|(_, note)| *note)
+            .collect()
+    }
+
+    /// Feeds a new pressure value, engaging at the threshold and releasing below it minus hysteresis
+    pub fn update(
+        &mut self,
+        pad: Pad,
+        binding: &PressureBinding,
+        pressure: u8,
+    ) -> Option<PressureChange> {
+        if !self.held.contains(&pad) {
+            return None;
+        }
+
+        if self.engaged.contains(&pad) {
+            if pressure <= binding.threshold.saturating_sub(binding.hysteresis) {
+                self.engaged.remove(&pad);
+                return Some(PressureChange::Release);
+            }
+        } else if pressure >= binding.threshold {
+            self.engaged.insert(pad);
+            return Some(PressureChange::Engage);
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PressureChange, PressureTracker};
+    use crate::{
+        config::{Action, PressureBinding},
+        midi::note::MidiNote,
+    };
+
+    fn binding() -> PressureBinding {
+        PressureBinding {
+            threshold: 100,
+            hysteresis: 10,
+            action: Action::Key(rdev::Key::ShiftLeft.into()),
+        }
+    }
+
+    #[test]
+    fn test_threshold_with_hysteresis() {
+        let pad = (0, MidiNote::C(4));
+        let binding = binding();
+        let mut tracker = PressureTracker::default();
+        tracker.press(pad);
+
+        assert_eq!(tracker.update(pad, &binding, 60), None);
+        assert_eq!(tracker.update(pad, &binding, 100), Some(PressureChange::Engage));
+        assert_eq!(tracker.update(pad, &binding, 127), None);
+        assert_eq!(tracker.update(pad, &binding, 95), None);
+        assert_eq!(tracker.update(pad, &binding, 90), Some(PressureChange::Release));
+        assert_eq!(tracker.update(pad, &binding, 95), None);
+    }
+
+    #[test]
+    fn test_release_resets_pad() {
+        let pad = (0, MidiNote::C(4));
+        let binding = binding();
+        let mut tracker = PressureTracker::default();
+
+        assert_eq!(tracker.update(pad, &binding, 127), None);
+
+        tracker.press(pad);
+        assert_eq!(tracker.update(pad, &binding, 127), Some(PressureChange::Engage));
+        assert_eq!(tracker.held_on(0), vec![MidiNote::C(4)]);
+        assert!(tracker.release(pad));
+        assert!(tracker.held_on(0).is_empty());
+        assert!(!tracker.release(pad));
+    }
+}
@@ -0,0 +1,77 @@
+use crate::{config::RadioGroup, midi::mapping::Control};
+
+/// Result of pressing a pad of a radio group
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Selection {
+    pub hold: bool,
+
+    /// The pad that was selected before, might be the pressed one again
+    pub previous: Option<Control>,
+}
+
+/// Remembers the selected pad of every radio group
+#[derive(Default)]
+pub struct RadioSelector {
+    groups: Vec<(RadioGroup, Option<Control>)>,
+}
+
+impl RadioSelector {
+    pub fn new(groups: Vec<RadioGroup>) -> Self {
+        Self {
+            groups: groups.into_iter().map(|group| (group, None)).collect(),
+        }
+    }
+
+    /// Selects the pad, returns `None` if it is not part of a group
+    pub fn select(&mut self, control: Control) -> Option<Selection> {
+        let (group, selected) = self
+            .groups
+            .iter_mut()
+            .find(|(group, _)| group.pads.iter().any(|key| key.matches(control)))?;
+
+        Some(Selection {
+            hold: group.hold,
+            previous: selected.replace(control),
+        })
+    }
+
+    pub fn is_selected(&self, control: Control) -> bool {
+        self.groups.iter().any(|(_, selected)| *selected == Some(control))
+    }
+
+    /// Deselects everything, returns the pads that were selected
+    pub fn reset(&mut self) -> Vec<Control> {
+        self.groups.iter_mut().filter_map(|(_, selected)| selected.take()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RadioSelector, Selection};
+    use crate::{
+        config::RadioGroup,
+        midi::{
+            mapping::{MappingKey, Trigger},
+            note::MidiNote,
+        },
+    };
+
+    #[test]
+    fn test_selection_moves() {
+        let pads = [MidiNote::C(1), MidiNote::D(1), MidiNote::E(1)];
+        let mut radio = RadioSelector::new(vec![RadioGroup {
+            pads: pads.iter().map(|note| MappingKey { channel: None, trigger: (*note).into() }).collect(),
+            hold: true,
+        }]);
+        let control = |i: usize| (0, Trigger::Note(pads[i]));
+
+        assert_eq!(radio.select(control(0)), Some(Selection { hold: true, previous: None }));
+        assert_eq!(radio.select(control(2)), Some(Selection { hold: true, previous: Some(control(0)) }));
+        assert!(radio.is_selected(control(2)));
+        assert!(!radio.is_selected(control(0)));
+        assert_eq!(radio.select((0, Trigger::Note(MidiNote::F(1)))), None);
+
+        assert_eq!(radio.reset(), vec![control(2)]);
+        assert!(!radio.is_selected(control(2)));
+    }
+}
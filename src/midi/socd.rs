@@ -0,0 +1,141 @@
+use crate::{
+    config::{SocdGroup, SocdResolution},
+    midi::mapping::Control,
+};
+
+/// What has to happen with the action of a pad in a group
+#[derive(Debug, PartialEq)]
+pub enum SocdChange {
+    Activate(Control),
+    Deactivate(Control),
+}
+
+struct GroupState {
+    group: SocdGroup,
+
+    /// The held control of each side and when it was pressed
+    held: [Option<(Control, u64)>; 2],
+}
+
+impl GroupState {
+    /// The side that is in effect
+    fn effective(&self) -> Option<Control> {
+        match self.held {
+            [None, None] => None,
+            [Some((c, _)), None] | [None, Some((c, _))] => Some(c),
+            [Some((a, at_a)), Some((b, at_b))] => match self.group.resolution {
+                SocdResolution::Neutral => None,
+                SocdResolution::Last => Some(if at_a > at_b { a } else { b }),
+                SocdResolution::First => Some(if at_a < at_b { a } else { b }),
+            },
+        }
+    }
+
+    fn side(&self, control: Control) -> Option<usize> {
+        self.group.pads.iter().position(|key| key.matches(control))
+    }
+}
+
+/// Resolves simultaneous opposite directions, pads outside of groups are left alone
+#[derive(Default)]
+pub struct SocdResolver {
+    groups: Vec<GroupState>,
+    presses: u64,
+}
+
+impl SocdResolver {
+    pub fn new(groups: Vec<SocdGroup>) -> Self {
+        Self {
+            groups: groups.into_iter().map(|group| GroupState { group, held: [None, None] }).collect(),
+            presses: 0,
+        }
+    }
+
+    /// Returns `None` if the control is not part of a group
+    pub fn press(&mut self, control: Control) -> Option<Vec<SocdChange>> {
+        self.presses += 1;
+        let at = self.presses;
+        self.update(control, Some((control, at)))
+    }
+
+    /// Returns `None` if the control is not part of a group
+    pub fn release(&mut self, control: Control) -> Option<Vec<SocdChange>> {
+        self.update(control, None)
+    }
+
+    /// Forgets all held pads
+    pub fn reset(&mut self) {
+        for state in self.groups.iter_mut() {
+            state.held = [None, None];
+        }
+    }
+
+    fn update(&mut self, control: Control, held: Option<(Control, u64)>) -> Option<Vec<SocdChange>> {
+        let state = self.groups.iter_mut().find(|s| s.side(control).is_some())?;
+        let side = state.side(control)?;
+
+        let before = state.effective();
+        state.held[side] = held;
+        let after = state.effective();
+
+        let mut changes = Vec::new();
+        if before != after {
+            changes.extend(before.map(SocdChange::Deactivate));
+            changes.extend(after.map(SocdChange::Activate));
+        }
+        Some(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SocdChange, SocdResolver};
+    use crate::{
+        config::{SocdGroup, SocdResolution},
+        midi::{
+            mapping::{MappingKey, Trigger},
+            note::MidiNote,
+        },
+    };
+
+    const LEFT: (u8, Trigger) = (0, Trigger::Note(MidiNote::C(3)));
+    const RIGHT: (u8, Trigger) = (0, Trigger::Note(MidiNote::E(3)));
+
+    fn resolver(resolution: SocdResolution) -> SocdResolver {
+        let key = |control: (u8, Trigger)| MappingKey { channel: None, trigger: control.1 };
+        SocdResolver::new(vec![SocdGroup { pads: [key(LEFT), key(RIGHT)], resolution }])
+    }
+
+    #[test]
+    fn test_last_input_wins() {
+        let mut socd = resolver(SocdResolution::Last);
+
+        assert_eq!(socd.press(LEFT), Some(vec![SocdChange::Activate(LEFT)]));
+        assert_eq!(
+            socd.press(RIGHT),
+            Some(vec![SocdChange::Deactivate(LEFT), SocdChange::Activate(RIGHT)])
+        );
+        assert_eq!(
+            socd.release(RIGHT),
+            Some(vec![SocdChange::Deactivate(RIGHT), SocdChange::Activate(LEFT)])
+        );
+        assert_eq!(socd.release(LEFT), Some(vec![SocdChange::Deactivate(LEFT)]));
+        assert_eq!(socd.press((0, Trigger::Note(MidiNote::D(3)))), None);
+    }
+
+    #[test]
+    fn test_neutral_and_first() {
+        let mut socd = resolver(SocdResolution::Neutral);
+        socd.press(LEFT);
+        assert_eq!(socd.press(RIGHT), Some(vec![SocdChange::Deactivate(LEFT)]));
+        assert_eq!(socd.release(LEFT), Some(vec![SocdChange::Activate(RIGHT)]));
+
+        let mut socd = resolver(SocdResolution::First);
+        socd.press(LEFT);
+        assert_eq!(socd.press(RIGHT), Some(vec![]));
+        assert_eq!(
+            socd.release(LEFT),
+            Some(vec![SocdChange::Deactivate(LEFT), SocdChange::Activate(RIGHT)])
+        );
+    }
+}
@@ -0,0 +1,69 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use log::debug;
+use once_cell::sync::Lazy;
+use rdev::Key;
+
+/// Keys currently held on the host, global so a panic hook can still release them
+pub static KEYS: Lazy<Mutex<KeyState>> = Lazy::new(|| Mutex::new(KeyState::default()));
+
+/// Counts the holders of every simulated key, so a key shared by several pads stays down until the last one lets go
+#[derive(Debug, Default)]
+pub struct KeyState {
+    holders: HashMap<Key, usize>,
+}
+
+impl KeyState {
+    /// Adds a holder, returns whether the key has to be pressed on the host
+    pub fn press(&mut self, key: Key) -> bool {
+        let count = self.holders.entry(key).or_default();
+        *count += 1;
+        *count == 1
+    }
+
+    /// Removes a holder, returns whether the key has to be released on the host.
+    /// Keys without holders were not pressed by us and are left alone.
+    pub fn release(&mut self, key: Key) -> bool {
+        match self.holders.get_mut(&key) {
+            Some(count) if *count > 1 => {
+                *count -= 1;
+                false
+            }
+            Some(_) => {
+                self.holders.remove(&key);
+                true
+            }
+            None => {
+                debug!("{:?} is not held, not releasing it", key);
+                false
+            }
+        }
+    }
+
+    /// Forgets all holders, returns the keys that are still down
+    pub fn drain(&mut self) -> Vec<Key> {
+        self.holders.drain().map(|(key, _)| key).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rdev::Key;
+
+    use super::KeyState;
+
+    #[test]
+    fn test_shared_key_released_by_last_holder() {
+        let mut keys = KeyState::default();
+
+        assert!(keys.press(Key::KeyW));
+        assert!(!keys.press(Key::KeyW));
+        assert!(!keys.release(Key::KeyW));
+        assert!(keys.release(Key::KeyW));
+        assert!(!keys.release(Key::KeyW));
+
+        assert!(keys.press(Key::KeyW));
+        assert_eq!(keys.drain(), vec![Key::KeyW]);
+        assert!(keys.drain().is_empty());
+    }
+}
@@ -0,0 +1,254 @@
+use log::warn;
+use rdev::Key;
+
+use crate::config::{KeyChord, KeyboardLayout, TextFallback, TextSettings};
+
+/// Chords typing a character one after another, using the fallback if the layout has no keys for it
+pub fn chords_for(settings: &TextSettings, c: char) -> Vec<KeyChord> {
+    if let Some(chord) = keys_for(settings.layout, c) {
+        return vec![chord];
+    }
+
+    match settings.fallback {
+        TextFallback::Skip => {
+            warn!("can not type {:?}", c);
+            Vec::new()
+        }
+        TextFallback::Unicode => {
+            let mut chords = vec![KeyChord(vec![Key::ControlLeft, Key::ShiftLeft, Key::KeyU])];
+            // hex digits are on the same keys in every supported layout
+            chords.extend(format!("{:x}", c as u32).chars().filter_map(|digit| keys_for(settings.layout, digit)));
+            chords.push(Key::Space.into());
+            chords
+        }
+    }
+}
+
+/// Keys typing a character with the given keyboard layout
+pub fn keys_for(layout: KeyboardLayout, c: char) -> Option<KeyChord> {
+    match layout {
+        KeyboardLayout::Us => us(c),
+        KeyboardLayout::De => de(c),
+    }
+}
+
+/// Lowercase of a character that has exactly one
+fn lowercase(c: char) -> Option<char> {
+    let mut lower = c.to_lowercase();
+    match (lower.next(), lower.next()) {
+        (Some(lower), None) if lower != c => Some(lower),
+        _ => None,
+    }
+}
+
+fn us(c: char) -> Option<KeyChord> {
+    if let Some(key) = us_unshifted(c) {
+        return Some(key.into());
+    }
+
+    let key = if c.is_ascii_uppercase() {
+        us_unshifted(c.to_ascii_lowercase())?
+    } else {
+        match c {
+            '~' => Key::BackQuote,
+            '!' => Key::Num1,
+            '@' => Key::Num2,
+            '#' => Key::Num3,
+            '$' => Key::Num4,
+            '%' => Key::Num5,
+            '^' => Key::Num6,
+            '&' => Key::Num7,
+            '*' => Key::Num8,
+            '(' => Key::Num9,
+            ')' => Key::Num0,
+            '_' => Key::Minus,
+            '+' => Key::Equal,
+            '{' => Key::LeftBracket,
+            '}' => Key::RightBracket,
+            '|' => Key::BackSlash,
+            ':' => Key::SemiColon,
+            '"' => Key::Quote,
+            '<' => Key::Comma,
+            '>' => Key::Dot,
+            '?' => Key::Slash,
+            _ => return None,
+        }
+    };
+
+    Some(KeyChord(vec![Key::ShiftLeft, key]))
+}
+
+fn us_unshifted(c: char) -> Option<Key> {
+    let key = match c {
+        'a' => Key::KeyA,
+        'b' => Key::KeyB,
+        'c' => Key::KeyC,
+        'd' => Key::KeyD,
+        'e' => Key::KeyE,
+        'f' => Key::KeyF,
+        'g' => Key::KeyG,
+        'h' => Key::KeyH,
+        'i' => Key::KeyI,
+        'j' => Key::KeyJ,
+        'k' => Key::KeyK,
+        'l' => Key::KeyL,
+        'm' => Key::KeyM,
+        'n' => Key::KeyN,
+        'o' => Key::KeyO,
+        'p' => Key::KeyP,
+        'q' => Key::KeyQ,
+        'r' => Key::KeyR,
+        's' => Key::KeyS,
+        't' => Key::KeyT,
+        'u' => Key::KeyU,
+        'v' => Key::KeyV,
+        'w' => Key::KeyW,
+        'x' => Key::KeyX,
+        'y' => Key::KeyY,
+        'z' => Key::KeyZ,
+        '1' => Key::Num1,
+        '2' => Key::Num2,
+        '3' => Key::Num3,
+        '4' => Key::Num4,
+        '5' => Key::Num5,
+        '6' => Key::Num6,
+        '7' => Key::Num7,
+        '8' => Key::Num8,
+        '9' => Key::Num9,
+        '0' => Key::Num0,
+        ' ' => Key::Space,
+        '\n' => Key::Return,
+        '\t' => Key::Tab,
+        '`' => Key::BackQuote,
+        '-' => Key::Minus,
+        '=' => Key::Equal,
+        '[' => Key::LeftBracket,
+        ']' => Key::RightBracket,
+        '\\' => Key::BackSlash,
+        ';' => Key::SemiColon,
+        '\'' => Key::Quote,
+        ',' => Key::Comma,
+        '.' => Key::Dot,
+        '/' => Key::Slash,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+/// German QWERTZ, dead keys like `^` and `´` are left to the fallback
+fn de(c: char) -> Option<KeyChord> {
+    if let Some(key) = de_unshifted(c) {
+        return Some(key.into());
+    }
+
+    if let Some(key) = lowercase(c).and_then(de_unshifted) {
+        return Some(KeyChord(vec![Key::ShiftLeft, key]));
+    }
+
+    let shifted = match c {
+        '°' => Some(Key::BackQuote),
+        '!' => Some(Key::Num1),
+        '"' => Some(Key::Num2),
+        '§' => Some(Key::Num3),
+        '$' => Some(Key::Num4),
+        '%' => Some(Key::Num5),
+        '&' => Some(Key::Num6),
+        '/' => Some(Key::Num7),
+        '(' => Some(Key::Num8),
+        ')' => Some(Key::Num9),
+        '=' => Some(Key::Num0),
+        '?' => Some(Key::Minus),
+        '*' => Some(Key::RightBracket),
+        '\'' => Some(Key::BackSlash),
+        '>' => Some(Key::IntlBackslash),
+        ';' => Some(Key::Comma),
+        ':' => Some(Key::Dot),
+        '_' => Some(Key::Slash),
+        _ => None,
+    };
+    if let Some(key) = shifted {
+        return Some(KeyChord(vec![Key::ShiftLeft, key]));
+    }
+
+    let key = match c {
+        '²' => Key::Num2,
+        '³' => Key::Num3,
+        '{' => Key::Num7,
+        '[' => Key::Num8,
+        ']' => Key::Num9,
+        '}' => Key::Num0,
+        '\\' => Key::Minus,
+        '@' => Key::KeyQ,
+        '€' => Key::KeyE,
+        '~' => Key::RightBracket,
+        '|' => Key::IntlBackslash,
+        'µ' => Key::KeyM,
+        _ => return None,
+    };
+
+    Some(KeyChord(vec![Key::AltGr, key]))
+}
+
+fn de_unshifted(c: char) -> Option<Key> {
+    let key = match c {
+        'y' => Key::KeyZ,
+        'z' => Key::KeyY,
+        'ß' => Key::Minus,
+        'ü' => Key::LeftBracket,
+        '+' => Key::RightBracket,
+        'ö' => Key::SemiColon,
+        'ä' => Key::Quote,
+        '#' => Key::BackSlash,
+        '<' => Key::IntlBackslash,
+        ',' => Key::Comma,
+        '.' => Key::Dot,
+        '-' => Key::Slash,
+        'a'..='z' | '0'..='9' | ' ' | '\n' | '\t' => us_unshifted(c)?,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use rdev::Key;
+
+    use super::{chords_for, keys_for};
+    use crate::config::{KeyChord, KeyboardLayout, TextFallback, TextSettings};
+
+    #[test]
+    fn test_german_layout() {
+        let keys = |c| keys_for(KeyboardLayout::De, c).map(|chord| chord.0);
+
+        assert_eq!(keys('z'), Some(vec![Key::KeyY]));
+        assert_eq!(keys('Y'), Some(vec![Key::ShiftLeft, Key::KeyZ]));
+        assert_eq!(keys('Ö'), Some(vec![Key::ShiftLeft, Key::SemiColon]));
+        assert_eq!(keys('?'), Some(vec![Key::ShiftLeft, Key::Minus]));
+        assert_eq!(keys('@'), Some(vec![Key::AltGr, Key::KeyQ]));
+        assert_eq!(keys('^'), None);
+        assert_eq!(keys_for(KeyboardLayout::Us, 'ö'), None);
+    }
+
+    #[test]
+    fn test_unicode_fallback() {
+        let mut settings = TextSettings::default();
+        assert!(chords_for(&settings, '👋').is_empty());
+
+        settings.fallback = TextFallback::Unicode;
+        assert_eq!(chords_for(&settings, 'a'), vec![KeyChord::from(Key::KeyA)]);
+        assert_eq!(
+            chords_for(&settings, '👋'),
+            vec![
+                KeyChord(vec![Key::ControlLeft, Key::ShiftLeft, Key::KeyU]),
+                Key::Num1.into(),
+                Key::KeyF.into(),
+                Key::Num4.into(),
+                Key::Num4.into(),
+                Key::KeyB.into(),
+                Key::Space.into(),
+            ]
+        );
+    }
+}
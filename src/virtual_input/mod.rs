@@ -0,0 +1,213 @@
+use log::{info, warn};
+use rdev::{EventType, display_size, simulate};
+use std::{error::Error, sync::TryLockError};
+
+use crate::{
+    config::{Action, Axis, AxisOptions},
+    virtual_input::keys::KEYS,
+};
+
+/// Characters to keys
+pub(crate) mod layout;
+
+/// Reference counted state of simulated keys
+pub(crate) mod keys;
+
+/// Sequences of key presses running in the background
+pub(crate) mod sequence;
+
+/// Keys repeating while a pad is held
+pub(crate) mod turbo;
+
+/// Processes of shell actions
+pub(crate) mod shell;
+
+/// Backend recording key events for tests
+#[cfg(test)]
+pub(crate) mod recorder;
+
+/// Default pixels per full travel of a control moving the mouse
+const DEFAULT_MOUSE_SCALE: f64 = 1000.0;
+
+/// Default wheel ticks per full travel of a control scrolling
+const DEFAULT_SCROLL_SCALE: f64 = 20.0;
+
+/// Value of a continuous control, normalized to 0.0..=1.0
+#[derive(Debug, Clone, Copy)]
+pub struct ControlValue {
+    pub value: f64,
+    /// The value the control had before, if it was seen already
+    pub previous: Option<f64>,
+}
+
+#[derive(Default)]
+struct AgnosticBackend {
+    /// Where we last put the cursor, rdev can neither ask the OS for it nor move it relatively
+    cursor: Option<(f64, f64)>,
+    /// Fractions of wheel ticks that were not sent yet
+    scroll: (f64, f64),
+}
+
+impl AgnosticBackend {
+    fn cursor(&mut self) -> (f64, f64) {
+        *self.cursor.get_or_insert_with(|| match display_size() {
+            Ok((w, h)) => (w as f64 / 2.0, h as f64 / 2.0),
+            Err(e) => {
+                warn!("could not get display size: {:?}", e);
+                (0.0, 0.0)
+            }
+        })
+    }
+
+    fn move_cursor(&mut self, x: f64, y: f64) {
+        self.cursor = Some((x, y));
+        simulate(&EventType::MouseMove { x, y }).expect("error moving mouse");
+    }
+}
+
+impl InputBackend for AgnosticBackend {
+    // shell actions are run by the input task, which tracks their processes per pad
+    fn process_on_action(&mut self, action: Action) {
+        if let Action::Key(chord) = action {
+            for key in chord.0 {
+                if KEYS.lock().unwrap().press(key) {
+                    simulate(&rdev::EventType::KeyPress(key)).expect("error sending key");
+                }
+            }
+        }
+    }
+
+    fn process_off_action(&mut self, action: Action) {
+        if let Action::Key(chord) = action {
+            for key in chord.0.into_iter().rev() {
+                if KEYS.lock().unwrap().release(key) {
+                    simulate(&rdev::EventType::KeyRelease(key)).expect("error sending key");
+                }
+            }
+        }
+    }
+
+    fn process_value_action(&mut self, action: Action, value: ControlValue) {
+        match action {
+            Action::Mouse(opts) => {
+                let Some(delta) = delta(&opts, value) else { return };
+                let delta = delta * opts.scale.unwrap_or(DEFAULT_MOUSE_SCALE);
+
+                let (x, y) = self.cursor();
+                match opts.axis {
+                    Axis::X => self.move_cursor(x + delta, y),
+                    Axis::Y => self.move_cursor(x, y + delta),
+                }
+            }
+            Action::Scroll(opts) => {
+                let Some(delta) = delta(&opts, value) else { return };
+                let delta = delta * opts.scale.unwrap_or(DEFAULT_SCROLL_SCALE);
+
+                let pending = match opts.axis {
+                    Axis::X => &mut self.scroll.0,
+                    Axis::Y => &mut self.scroll.1,
+                };
+                *pending += delta;
+
+                let ticks = pending.trunc();
+                if ticks == 0.0 {
+                    return;
+                }
+                *pending -= ticks;
+
+                let (delta_x, delta_y) = match opts.axis {
+                    Axis::X => (ticks as i64, 0),
+                    Axis::Y => (0, ticks as i64),
+                };
+                simulate(&EventType::Wheel { delta_x, delta_y }).expect("error scrolling");
+            }
+            Action::Position(opts) => {
+                let [min, max] = match opts.range {
+                    Some(range) => range,
+                    None => {
+                        let (w, h) = display_size().unwrap_or((0, 0));
+                        match opts.axis {
+                            Axis::X => [0.0, w as f64],
+                            Axis::Y => [0.0, h as f64],
+                        }
+                    }
+                };
+                let position = min + opts.shape(value.value) * (max - min);
+
+                let (x, y) = self.cursor();
+                match opts.axis {
+                    Axis::X => self.move_cursor(position, y),
+                    Axis::Y => self.move_cursor(x, position),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn release_all(&mut self) {
+        release_all();
+    }
+}
+
+/// Releases every key still held on the host, safe to call from a panic hook
+pub fn release_all() {
+    let keys = match KEYS.try_lock() {
+        Ok(mut keys) => keys.drain(),
+        Err(TryLockError::Poisoned(e)) => e.into_inner().drain(),
+        // the panic happened while the keys were locked
+        Err(TryLockError::WouldBlock) => return,
+    };
+
+    for key in keys {
+        info!("releasing {:?}", key);
+        if let Err(e) = simulate(&EventType::KeyRelease(key)) {
+            warn!("could not release {:?}: {:?}", key, e);
+        }
+    }
+}
+
+/// Change of a control since its last value, after deadzone and inversion
+fn delta(opts: &AxisOptions, value: ControlValue) -> Option<f64> {
+    let previous = value.previous?;
+    let delta = opts.shape(value.value) - opts.shape(previous);
+
+    (delta != 0.0).then_some(delta)
+}
+
+pub trait InputBackend: Send + Sync {
+    fn process_on_action(&mut self, action: Action);
+    fn process_off_action(&mut self, action: Action);
+    fn process_value_action(&mut self, action: Action, value: ControlValue);
+
+    /// Releases everything that is still held down
+    fn release_all(&mut self);
+}
+
+pub fn create_backend() -> Result<Box<dyn InputBackend>, Box<dyn Error>> {
+    Ok(Box::new(AgnosticBackend::default()))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{ControlValue, delta};
+    use crate::config::{Axis, AxisOptions};
+
+    #[test]
+    fn test_delta() {
+        let opts = AxisOptions {
+            axis: Axis::Y,
+            scale: None,
+            deadzone: 0.25,
+            range: None,
+            invert: true,
+        };
+        let value = |value, previous| ControlValue { value, previous };
+
+        // nothing to compare with on the first value
+        assert_eq!(delta(&opts, value(1.0, None)), None);
+        assert_eq!(delta(&opts, value(0.6, Some(0.4))), None);
+        assert_eq!(delta(&opts, value(0.0, Some(0.5))), Some(0.5));
+        assert_eq!(delta(&opts, value(1.0, Some(0.0))), Some(-1.0));
+    }
+}
@@ -0,0 +1,43 @@
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::{
+    config::Action,
+    virtual_input::{ControlValue, InputBackend},
+};
+
+/// Key events in the order they were sent
+pub(crate) type Events = Arc<std::sync::Mutex<Vec<String>>>;
+
+/// Remembers key events as `+Key` and `-Key`, chords are released in reverse
+struct Recorder(Events);
+
+impl InputBackend for Recorder {
+    fn process_on_action(&mut self, action: Action) {
+        if let Action::Key(chord) = action {
+            for key in chord.0 {
+                self.0.lock().unwrap().push(format!("+{:?}", key));
+            }
+        }
+    }
+
+    fn process_off_action(&mut self, action: Action) {
+        if let Action::Key(chord) = action {
+            for key in chord.0.into_iter().rev() {
+                self.0.lock().unwrap().push(format!("-{:?}", key));
+            }
+        }
+    }
+
+    fn process_value_action(&mut self, _action: Action, _value: ControlValue) {}
+
+    fn release_all(&mut self) {}
+}
+
+/// A shared recording backend and the events it records
+pub(crate) fn recorder() -> (Arc<Mutex<Box<dyn InputBackend>>>, Events) {
+    let events = Events::default();
+    let backend: Box<dyn InputBackend> = Box::new(Recorder(events.clone()));
+    (Arc::new(Mutex::new(backend)), events)
+}
@@ -0,0 +1,273 @@
+use std::{sync::Arc, time::Duration};
+
+use log::debug;
+use tokio::{
+    sync::{
+        Mutex,
+        oneshot::{self, error::TryRecvError},
+    },
+    task::JoinHandle,
+};
+
+use crate::{
+    config::{Action, KeyChord, Retrigger, Sequence, Step, TextSettings},
+    virtual_input::{InputBackend, layout},
+};
+
+/// A sequence running in the background, dropping it stops the sequence
+pub struct RunningSequence {
+    handle: JoinHandle<()>,
+    _cancel: oneshot::Sender<()>,
+}
+
+impl RunningSequence {
+    fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+}
+
+/// Starts a sequence, honoring its retrigger mode if the previous run of the same pad is still going
+pub fn start(
+    backend: Arc<Mutex<Box<dyn InputBackend>>>,
+    sequence: Sequence,
+    text: TextSettings,
+    previous: Option<RunningSequence>,
+) -> RunningSequence {
+    let previous = previous.filter(|p| !p.is_finished());
+
+    let previous = match (sequence.retrigger, previous) {
+        (Retrigger::Ignore, Some(previous)) => {
+            debug!("sequence still running, ignoring press");
+            return previous;
+        }
+        (Retrigger::Restart, Some(previous)) => {
+            debug!("restarting sequence");
+            drop(previous);
+            None
+        }
+        (_, previous) => previous,
+    };
+
+    let (cancel, mut cancelled) = oneshot::channel();
+    let handle = tokio::spawn(async move {
+        // queued runs start once the previous one is done, stopping them stops the previous one as well
+        if let Some(previous) = previous {
+            let RunningSequence { handle, _cancel } = previous;
+            tokio::select! {
+                _ = handle => {}
+                _ = &mut cancelled => return,
+            }
+        }
+
+        run(backend, sequence.steps, &text, cancelled).await;
+    });
+
+    RunningSequence {
+        handle,
+        _cancel: cancel,
+    }
+}
+
+/// Executes the steps, the backend is only locked for single key events
+async fn run(
+    backend: Arc<Mutex<Box<dyn InputBackend>>>,
+    steps: Vec<Step>,
+    text: &TextSettings,
+    mut cancelled: oneshot::Receiver<()>,
+) {
+    let mut pressed: Vec<KeyChord> = Vec::new();
+    let mut stopped = false;
+
+    for step in steps {
+        if !matches!(cancelled.try_recv(), Err(TryRecvError::Empty)) {
+            stopped = true;
+            break;
+        }
+
+        match step {
+            Step::Press(chord) => {
+                press(&backend, chord.clone()).await;
+                pressed.push(chord);
+            }
+            Step::Release(chord) => {
+                pressed.retain(|c| *c != chord);
+                release(&backend, chord).await;
+            }
+            Step::Tap(chord) => {
+                press(&backend, chord.clone()).await;
+                release(&backend, chord).await;
+            }
+            Step::Wait(ms) => {
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_millis(ms)) => {}
+                    _ = &mut cancelled => {
+                        stopped = true;
+                        break;
+                    }
+                }
+            }
+            Step::Text(string) => {
+                if !type_text(&backend, &string, text, &mut cancelled).await {
+                    stopped = true;
+                    break;
+                }
+            }
+        }
+    }
+
+    // don't leave keys of an interrupted sequence stuck
+    if stopped {
+        for chord in pressed.into_iter().rev() {
+            release(&backend, chord).await;
+        }
+    }
+}
+
+/// Types the characters one by one with the configured delay, returns false if cancelled before a character
+async fn type_text(
+    backend: &Arc<Mutex<Box<dyn InputBackend>>>,
+    string: &str,
+    text: &TextSettings,
+    cancelled: &mut oneshot::Receiver<()>,
+) -> bool {
+    for c in string.chars() {
+        if !matches!(cancelled.try_recv(), Err(TryRecvError::Empty)) {
+            return false;
+        }
+
+        for chord in layout::chords_for(text, c) {
+            press(backend, chord.clone()).await;
+            release(backend, chord).await;
+        }
+
+        if text.delay_ms > 0 {
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_millis(text.delay_ms)) => {}
+                _ = &mut *cancelled => return false,
+            }
+        }
+    }
+    true
+}
+
+async fn press(backend: &Arc<Mutex<Box<dyn InputBackend>>>, chord: KeyChord) {
+    backend.lock().await.process_on_action(Action::Key(chord));
+}
+
+async fn release(backend: &Arc<Mutex<Box<dyn InputBackend>>>, chord: KeyChord) {
+    backend.lock().await.process_off_action(Action::Key(chord));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rdev::Key;
+
+    use super::start;
+    use crate::{
+        config::{KeyboardLayout, Retrigger, Sequence, Step, TextSettings},
+        virtual_input::recorder::recorder,
+    };
+
+    #[tokio::test]
+    async fn test_steps() {
+        let (backend, events) = recorder();
+        let sequence = Sequence {
+            steps: vec![
+                Step::Tap(Key::KeyG.into()),
+                Step::Wait(5),
+                Step::Text("a!".to_string()),
+            ],
+            retrigger: Retrigger::Ignore,
+        };
+
+        let running = start(backend, sequence, TextSettings::default(), None);
+        running.handle.await.unwrap();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec!["+KeyG", "-KeyG", "+KeyA", "-KeyA", "+ShiftLeft", "+Num1", "-Num1", "-ShiftLeft"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_text_delay_and_layout() {
+        let (backend, events) = recorder();
+        let sequence = Sequence {
+            steps: vec![Step::Text("zzzz".to_string())],
+            retrigger: Retrigger::Ignore,
+        };
+        let text = TextSettings {
+            layout: KeyboardLayout::De,
+            delay_ms: 50,
+            ..Default::default()
+        };
+
+        let running = start(backend, sequence, text, None);
+        tokio::time::sleep(Duration::from_millis(75)).await;
+        // cancelling stops typing between characters
+        drop(running);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        assert_eq!(*events.lock().unwrap(), vec!["+KeyY", "-KeyY", "+KeyY", "-KeyY"]);
+    }
+
+    #[tokio::test]
+    async fn test_text_without_delay_can_be_cancelled() {
+        let (backend, events) = recorder();
+        let sequence = Sequence {
+            steps: vec![Step::Text("abcdef".to_string())],
+            retrigger: Retrigger::Ignore,
+        };
+
+        // typing waits for the backend on the first character
+        let locked = backend.clone().lock_owned().await;
+        let running = start(backend, sequence, TextSettings::default(), None);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        drop(running);
+        drop(locked);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        assert_eq!(*events.lock().unwrap(), vec!["+KeyA", "-KeyA"]);
+    }
+
+    #[tokio::test]
+    async fn test_dropping_a_queued_run_stops_the_running_one() {
+        let (backend, events) = recorder();
+        let sequence = Sequence {
+            steps: vec![Step::Press(Key::KeyW.into()), Step::Wait(1000), Step::Release(Key::KeyW.into())],
+            retrigger: Retrigger::Queue,
+        };
+
+        let first = start(backend.clone(), sequence.clone(), TextSettings::default(), None);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let queued = start(backend, sequence, TextSettings::default(), Some(first));
+        drop(queued);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        assert_eq!(*events.lock().unwrap(), vec!["+KeyW", "-KeyW"]);
+    }
+
+    #[tokio::test]
+    async fn test_restart_releases_held_keys() {
+        let (backend, events) = recorder();
+        let sequence = |retrigger| Sequence {
+            steps: vec![Step::Press(Key::KeyW.into()), Step::Wait(1000)],
+            retrigger,
+        };
+
+        let first = start(backend.clone(), sequence(Retrigger::Restart), TextSettings::default(), None);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        // ignoring keeps the first run going
+        let first = start(backend.clone(), sequence(Retrigger::Ignore), TextSettings::default(), Some(first));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(*events.lock().unwrap(), vec!["+KeyW"]);
+
+        let second = start(backend.clone(), sequence(Retrigger::Restart), TextSettings::default(), Some(first));
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(*events.lock().unwrap(), vec!["+KeyW", "-KeyW", "+KeyW"]);
+        drop(second);
+    }
+}
@@ -0,0 +1,389 @@
+use std::{
+    io,
+    process::{ExitStatus, Stdio},
+    time::{Duration, Instant},
+};
+
+use log::{Level, info, log, warn};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader},
+    process::{Child, Command},
+    sync::oneshot,
+    task::JoinHandle,
+};
+
+use crate::config::{CommandLine, CommandOptions, CommandOutput};
+
+/// Builds the process of a shell action, plain strings run through the shell
+fn command(options: &CommandOptions) -> io::Result<Command> {
+    let mut command = match &options.command {
+        #[cfg(unix)]
+        CommandLine::Shell(line) => {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(line);
+            command
+        }
+        #[cfg(windows)]
+        CommandLine::Shell(line) => {
+            let mut command = Command::new("cmd");
+            command.arg("/C").arg(line);
+            command
+        }
+        CommandLine::Argv(argv) => {
+            let Some((program, args)) = argv.split_first() else {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "empty command"));
+            };
+            let mut command = Command::new(program);
+            command.args(args);
+            command
+        }
+    };
+
+    // signals reach everything the shell started
+    #[cfg(unix)]
+    command.process_group(0);
+
+    command.envs(&options.env);
+    if let Some(cwd) = &options.cwd {
+        command.current_dir(cwd);
+    }
+    if options.stdin.is_some() {
+        command.stdin(Stdio::piped());
+    }
+    Ok(command)
+}
+
+/// Starts the command, feeds its standard input and forwards its output in the background
+pub fn spawn(options: &CommandOptions, output: &CommandOutput, pad: &str) -> io::Result<Child> {
+    let mut command = command(options)?;
+    match output {
+        CommandOutput::Inherit => {}
+        CommandOutput::Log => {
+            command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        }
+        CommandOutput::File(path) => {
+            let file = std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+            command.stdout(file.try_clone()?).stderr(file);
+        }
+        CommandOutput::Discard => {
+            command.stdout(Stdio::null()).stderr(Stdio::null());
+        }
+    }
+    let mut child = command.spawn()?;
+
+    if let (Some(input), Some(mut stdin)) = (options.stdin.clone(), child.stdin.take()) {
+        tokio::spawn(async move {
+            if let Err(e) = stdin.write_all(input.as_bytes()).await {
+                warn!("could not write to stdin: {}", e);
+            }
+        });
+    }
+    if let Some(stdout) = child.stdout.take() {
+        log_lines(pad.to_string(), stdout, Level::Info);
+    }
+    if let Some(stderr) = child.stderr.take() {
+        log_lines(pad.to_string(), stderr, Level::Warn);
+    }
+    Ok(child)
+}
+
+/// Logs every line of the output until the command closes it
+fn log_lines<R>(pad: String, output: R, level: Level)
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(output).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            log!(level, "{}: {}", pad, line);
+        }
+    });
+}
+
+/// How long a terminated command gets to exit before it is killed
+const TERMINATE_GRACE: Duration = Duration::from_secs(2);
+
+/// A command supervised in the background until it exited and was reaped
+pub struct RunningCommand {
+    handle: JoinHandle<()>,
+    terminate: Option<oneshot::Sender<()>>,
+}
+
+impl RunningCommand {
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Asks the command to exit, it is killed if it does not within the grace period
+    pub fn terminate(&mut self) {
+        if let Some(terminate) = self.terminate.take() {
+            let _ = terminate.send(());
+        }
+    }
+
+    /// Waits until the command was reaped
+    pub async fn join(self) {
+        let _ = self.handle.await;
+    }
+}
+
+/// Reported while a command runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandStatus {
+    Running,
+
+    /// The command is gone, whether it was successful
+    Exited(bool),
+}
+
+/// How a command started by a pad is run
+#[derive(Debug, Clone)]
+pub struct Supervision {
+    /// Name of the pad, the context of the logged output
+    pub pad: String,
+
+    /// Terminates the command after this time
+    pub timeout: Option<Duration>,
+
+    pub output: CommandOutput,
+}
+
+/// Starts a command, failures to start are logged and reported as failed exit
+pub fn start<F>(options: CommandOptions, supervision: Supervision, on_status: F) -> Option<RunningCommand>
+where
+    F: Fn(CommandStatus) + Send + 'static,
+{
+    info!("{}: running {:?}", supervision.pad, options.command);
+    let child = match spawn(&options, &supervision.output, &supervision.pad) {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("{}: could not run {:?}: {}", supervision.pad, options.command, e);
+            on_status(CommandStatus::Exited(false));
+            return None;
+        }
+    };
+    on_status(CommandStatus::Running);
+
+    let (terminate, terminated) = oneshot::channel();
+    let handle = tokio::spawn(async move {
+        let success = supervise(child, options, supervision, terminated).await;
+        on_status(CommandStatus::Exited(success));
+    });
+    Some(RunningCommand {
+        handle,
+        terminate: Some(terminate),
+    })
+}
+
+/// Waits for the command to exit and records how it went, returns whether it was successful
+async fn supervise(
+    mut child: Child,
+    options: CommandOptions,
+    supervision: Supervision,
+    terminated: oneshot::Receiver<()>,
+) -> bool {
+    let started = Instant::now();
+    let timeout = async {
+        match supervision.timeout {
+            Some(timeout) => tokio::time::sleep(timeout).await,
+            None => std::future::pending().await,
+        }
+    };
+
+    let status = tokio::select! {
+        status = child.wait() => status,
+        Ok(()) = terminated => stop(&mut child).await,
+        _ = timeout => {
+            warn!("{}: {:?} timed out", supervision.pad, options.command);
+            stop(&mut child).await
+        }
+    };
+
+    let status = match status {
+        Ok(status) => status,
+        Err(e) => {
+            warn!("{}: could not wait for {:?}: {}", supervision.pad, options.command, e);
+            return false;
+        }
+    };
+    record(&options, &supervision, status, started.elapsed()).await;
+    status.success()
+}
+
+/// Logs the exit status and duration of a run, also to the output file if there is one
+async fn record(options: &CommandOptions, supervision: &Supervision, status: ExitStatus, duration: Duration) {
+    let level = if status.success() { Level::Info } else { Level::Warn };
+    let run = format!("{:?} {} after {:.2?}", options.command, status, duration);
+    log!(level, "{}: {}", supervision.pad, run);
+
+    if let CommandOutput::File(path) = &supervision.output {
+        let line = format!("[{}] {}\n", supervision.pad, run);
+        let written = async {
+            let mut file = tokio::fs::OpenOptions::new().append(true).open(path).await?;
+            file.write_all(line.as_bytes()).await?;
+            file.flush().await
+        };
+        if let Err(e) = written.await {
+            warn!("could not write to {}: {}", path, e);
+        }
+    }
+}
+
+/// Sends SIGTERM to the process group of the command, kills the group if the command is still there after the grace period
+async fn stop(child: &mut Child) -> io::Result<ExitStatus> {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // SAFETY: plain syscall, the group was created with the child
+        unsafe { libc::kill(-(pid as i32), libc::SIGTERM) };
+    }
+    #[cfg(not(unix))]
+    let _ = child.start_kill();
+
+    match tokio::time::timeout(TERMINATE_GRACE, child.wait()).await {
+        Ok(status) => status,
+        Err(_) => {
+            // grandchildren ignoring SIGTERM go down with the group
+            #[cfg(unix)]
+            if let Some(pid) = child.id() {
+                // SAFETY: plain syscall, the group was created with the child
+                unsafe { libc::kill(-(pid as i32), libc::SIGKILL) };
+            }
+            child.kill().await?;
+            child.wait().await
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    use super::{CommandStatus, Supervision, spawn, start};
+    use crate::config::{CommandLine, CommandOptions, CommandOutput};
+
+    fn options(command: CommandLine) -> CommandOptions {
+        CommandOptions {
+            command,
+            env: HashMap::new(),
+            cwd: None,
+            stdin: None,
+        }
+    }
+
+    fn supervision(timeout: Option<Duration>, output: CommandOutput) -> Supervision {
+        Supervision {
+            pad: "C5".to_string(),
+            timeout,
+            output,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_options_reach_the_process() {
+        let mut options = options(CommandLine::Shell(
+            r#"read line && test "$line" = hello && test "$GREETING" = hi && test "$(pwd)" = /"#.to_string(),
+        ));
+        options.env.insert("GREETING".to_string(), "hi".to_string());
+        options.cwd = Some("/".to_string());
+        options.stdin = Some("hello\n".to_string());
+
+        let status = spawn(&options, &CommandOutput::Inherit, "C5").unwrap().wait().await.unwrap();
+        assert!(status.success());
+    }
+
+    #[tokio::test]
+    async fn test_argv_is_not_split() {
+        let argv = vec!["test".to_string(), "a b".to_string(), "=".to_string(), "a b".to_string()];
+        let status = spawn(&options(CommandLine::Argv(argv)), &CommandOutput::Inherit, "C5").unwrap().wait().await.unwrap();
+        assert!(status.success());
+
+        let spawn = |argv| spawn(&options(CommandLine::Argv(argv)), &CommandOutput::Inherit, "C5");
+        assert!(spawn(vec![]).is_err());
+        assert!(spawn(vec!["/does/not/exist".to_string()]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_terminate_and_timeout() {
+        let sleep = || options(CommandLine::Shell("sleep 10".to_string()));
+        let started = Instant::now();
+
+        let mut running = start(sleep(), supervision(None, CommandOutput::Inherit), |_| {}).unwrap();
+        assert!(!running.is_finished());
+        running.terminate();
+        running.join().await;
+
+        start(sleep(), supervision(Some(Duration::from_millis(50)), CommandOutput::Inherit), |_| {}).unwrap().join().await;
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_group_is_killed_after_grace() {
+        let path = std::env::temp_dir().join(format!("shell-grandchild-{}.pid", std::process::id()));
+        let script = format!("(trap '' TERM; exec sleep 30) & echo $! > {}; trap '' TERM; wait", path.display());
+
+        let mut running = start(options(CommandLine::Shell(script)), supervision(None, CommandOutput::Inherit), |_| {}).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        running.terminate();
+        running.join().await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let pid = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // gone, or a zombie nobody reaped yet
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid.trim())).unwrap_or_default();
+        assert!(stat.is_empty() || stat.contains(") Z "), "{}", stat);
+    }
+
+    #[tokio::test]
+    async fn test_status_is_reported() {
+        let statuses = Arc::new(Mutex::new(Vec::new()));
+        let report = |statuses: &Arc<Mutex<Vec<CommandStatus>>>| {
+            let statuses = statuses.clone();
+            move |status| statuses.lock().unwrap().push(status)
+        };
+
+        let run = |command: &str| {
+            let command = options(CommandLine::Shell(command.to_string()));
+            start(command, supervision(None, CommandOutput::Discard), report(&statuses))
+        };
+        run("true").unwrap().join().await;
+        run("exit 1").unwrap().join().await;
+
+        let empty = options(CommandLine::Argv(vec![]));
+        assert!(start(empty, supervision(None, CommandOutput::Discard), report(&statuses)).is_none());
+
+        assert_eq!(
+            *statuses.lock().unwrap(),
+            vec![
+                CommandStatus::Running,
+                CommandStatus::Exited(true),
+                CommandStatus::Running,
+                CommandStatus::Exited(false),
+                CommandStatus::Exited(false),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_output_is_appended_to_file() {
+        let path = std::env::temp_dir().join(format!("shell-output-{}.log", std::process::id()));
+        let output = CommandOutput::File(path.to_string_lossy().to_string());
+        let _ = std::fs::remove_file(&path);
+
+        for _ in 0..2 {
+            let command = options(CommandLine::Shell("echo out; echo err >&2; exit 3".to_string()));
+            start(command, supervision(None, output.clone()), |_| {}).unwrap().join().await;
+        }
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = written.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(&lines[..2], ["out", "err"]);
+        assert!(lines[2].starts_with("[C5] ") && lines[2].contains("exit status: 3 after"));
+    }
+}
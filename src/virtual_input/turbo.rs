@@ -0,0 +1,131 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use tokio::{
+    sync::{Mutex, watch},
+    task::JoinHandle,
+};
+
+use crate::{
+    config::{Action, KeyChord},
+    virtual_input::InputBackend,
+};
+
+/// Slowest repeat rate in taps per second, keeps a rate of zero from stalling the task
+const MIN_RATE: f64 = 0.1;
+
+/// A key tapping repeatedly in the background until it is stopped
+pub struct Turbo {
+    handle: JoinHandle<()>,
+    rate: watch::Sender<f64>,
+    backend: Arc<Mutex<Box<dyn InputBackend>>>,
+    chord: KeyChord,
+
+    /// Whether the key is down right now, other pads might hold it as well
+    pressed: Arc<AtomicBool>,
+}
+
+impl Turbo {
+    /// Starts tapping at `rate` taps per second, `blink` is called with the key state on every change
+    pub fn start<F>(
+        backend: Arc<Mutex<Box<dyn InputBackend>>>,
+        chord: KeyChord,
+        rate: f64,
+        blink: F,
+    ) -> Self
+    where
+        F: Fn(bool) + Send + 'static,
+    {
+        let (rate, rate_rx) = watch::channel(rate);
+        let pressed = Arc::new(AtomicBool::new(false));
+        let handle = tokio::spawn(run(backend.clone(), chord.clone(), rate_rx, pressed.clone(), blink));
+
+        Turbo {
+            handle,
+            rate,
+            backend,
+            chord,
+            pressed,
+        }
+    }
+
+    /// Changes the rate, takes effect with the next tap
+    pub fn set_rate(&self, rate: f64) {
+        let _ = self.rate.send(rate);
+    }
+
+    /// Stops tapping and makes sure the key is not left pressed
+    pub async fn stop(self) {
+        self.handle.abort();
+        // the task might be in the middle of a tap on another thread
+        let _ = self.handle.await;
+
+        if self.pressed.load(Ordering::SeqCst) {
+            self.backend
+                .lock()
+                .await
+                .process_off_action(Action::Key(self.chord));
+        }
+    }
+}
+
+async fn run<F>(
+    backend: Arc<Mutex<Box<dyn InputBackend>>>,
+    chord: KeyChord,
+    rate: watch::Receiver<f64>,
+    pressed: Arc<AtomicBool>,
+    blink: F,
+) where
+    F: Fn(bool),
+{
+    loop {
+        let half_period = Duration::from_secs_f64(0.5 / rate.borrow().max(MIN_RATE));
+
+        backend
+            .lock()
+            .await
+            .process_on_action(Action::Key(chord.clone()));
+        pressed.store(true, Ordering::SeqCst);
+        blink(true);
+        tokio::time::sleep(half_period).await;
+
+        backend
+            .lock()
+            .await
+            .process_off_action(Action::Key(chord.clone()));
+        pressed.store(false, Ordering::SeqCst);
+        blink(false);
+        tokio::time::sleep(half_period).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use rdev::Key;
+
+    use super::Turbo;
+    use crate::virtual_input::recorder::recorder;
+
+    #[tokio::test]
+    async fn test_stop_releases_only_a_pressed_key() {
+        let (backend, events) = recorder();
+
+        // 5 taps per second, pressed for the first 100ms of every 200ms
+        let turbo = Turbo::start(backend.clone(), Key::KeyW.into(), 5.0, |_| {});
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        turbo.stop().await;
+        assert_eq!(*events.lock().unwrap(), vec!["+KeyW", "-KeyW"]);
+
+        let turbo = Turbo::start(backend, Key::KeyW.into(), 5.0, |_| {});
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        turbo.stop().await;
+        assert_eq!(*events.lock().unwrap(), vec!["+KeyW", "-KeyW", "+KeyW", "-KeyW"]);
+    }
+}